@@ -0,0 +1,250 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{Result as CatResult, anyhow};
+use teacat_lib::prelude::*;
+use walkdir::{DirEntry, WalkDir};
+
+use crate::{config::Config, eval, eval_html, report::Reporter};
+
+/// What is done with a file within the source directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+	/// A TeaCat file, rendered to the provided path.
+	Page(PathBuf),
+	/// Any other file, copied to the provided path.
+	Asset(PathBuf),
+	/// A TeaCat file that is only used by other files, so is not rendered.
+	Partial,
+}
+
+/// Renders every TeaCat file within a directory into HTML (with the same path within the
+/// output directory), and copies every other file as-is.
+///
+/// Errors do not stop the build, and are instead all reported once every file has been built.
+pub fn build(src: &Path, out_dir: &Path, config: &Config, reporter: &Reporter) -> CatResult<()> {
+	let mut errors = vec![];
+
+	for entry in entries(src, Some(out_dir), config) {
+		let result = entry.map_err(Into::into).and_then(|entry| {
+			if !entry.file_type().is_file() {
+				return Ok(());
+			}
+
+			let file = entry.path();
+			let relative = file.strip_prefix(src)?;
+
+			match target(relative) {
+				Target::Page(out) => {
+					let html = eval_html(file, config, reporter)?;
+					write(&out_dir.join(out), html)
+				}
+				Target::Asset(out) => {
+					let contents = fs::read(file).map_err(|err| TeaCatError::io(file, err))?;
+					write(&out_dir.join(out), contents)
+				}
+				Target::Partial => Ok(()),
+			}
+		});
+
+		if let Err(err) = result {
+			errors.push(err);
+		}
+	}
+
+	for err in &errors {
+		reporter.error(err);
+	}
+
+	match errors.len() {
+		0 => Ok(()),
+		len => Err(anyhow!("could not build {len} file(s)")),
+	}
+}
+
+/// Evaluates TeaCat files without writing anything. Directories are checked in the same way
+/// as [build], so every TeaCat file within them is checked except for partials.
+///
+/// Errors do not stop the check, and are instead all reported once every file has been checked.
+pub fn check(paths: &[&Path], config: &Config, reporter: &Reporter) -> CatResult<()> {
+	let mut errors = vec![];
+
+	for path in paths {
+		let files: Vec<CatResult<PathBuf>> = if path.is_dir() {
+			entries(path, None, config)
+				.filter_map(|entry| match entry {
+					Ok(entry) => {
+						let relative = entry.path().strip_prefix(path).ok()?;
+						(entry.file_type().is_file() && matches!(target(relative), Target::Page(_)))
+							.then(|| Ok(entry.into_path()))
+					}
+					Err(err) => Some(Err(err.into())),
+				})
+				.collect()
+		} else {
+			vec![Ok(path.to_path_buf())]
+		};
+
+		for file in files {
+			if let Err(err) = file.and_then(|file| eval::<HtmlRenderer>(&file, config, reporter)) {
+				errors.push(err);
+			}
+		}
+	}
+
+	for err in &errors {
+		reporter.error(err);
+	}
+
+	match errors.len() {
+		0 => Ok(()),
+		len => Err(anyhow!("{len} file(s) contain errors")),
+	}
+}
+
+/// Walks through everything within the source directory, except for the output directory
+/// (which may be within it) and anything ignored by the [Config].
+pub fn entries(
+	src: &Path,
+	out_dir: Option<&Path>,
+	config: &Config,
+) -> impl Iterator<Item = walkdir::Result<DirEntry>> + use<> {
+	let out_canonical = out_dir.and_then(|out_dir| fs::canonicalize(out_dir).ok());
+	let (src, config) = (src.to_path_buf(), config.clone());
+
+	WalkDir::new(&src)
+		.sort_by_file_name()
+		.into_iter()
+		.filter_entry(move |entry| {
+			let relative = entry.path().strip_prefix(&src).unwrap_or(entry.path());
+			// Only directories can be the output directory, so files are never canonicalized
+			let is_out_dir = || {
+				out_canonical.is_some()
+					&& entry.file_type().is_dir()
+					&& fs::canonicalize(entry.path()).ok() == out_canonical
+			};
+			!config.is_ignored(relative) && !is_out_dir()
+		})
+}
+
+/// Finds what is done with a file, using its path within the source directory.
+fn target(relative: &Path) -> Target {
+	if !is_teacat(relative) {
+		Target::Asset(relative.into())
+	} else if is_partial(relative) {
+		Target::Partial
+	} else {
+		Target::Page(relative.with_extension("html"))
+	}
+}
+
+pub fn is_teacat(path: &Path) -> bool {
+	path.extension().is_some_and(|ext| ext == "tcat")
+}
+
+/// Whether a TeaCat file is a partial, using its path within the source directory. This is
+/// the case if its name (or the name of any directory it is within) starts with an underscore,
+/// such as `_nav.tcat` or `_partials/nav.tcat`.
+pub fn is_partial(relative: &Path) -> bool {
+	is_teacat(relative)
+		&& relative
+			.components()
+			.any(|component| component.as_os_str().to_string_lossy().starts_with('_'))
+}
+
+/// Writes to a file, creating the directories it is within if needed. Files that already
+/// have the same contents are left untouched.
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> CatResult<()> {
+	let contents = contents.as_ref();
+	if fs::read(path).is_ok_and(|prev| prev == contents) {
+		return Ok(());
+	}
+
+	create_parent(path)?;
+	fs::write(path, contents).map_err(|err| TeaCatError::io(path, err))?;
+	Ok(())
+}
+
+fn create_parent(path: &Path) -> CatResult<()> {
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent).map_err(|err| TeaCatError::io(parent, err))?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use teacat_lib::testing::TempDir;
+
+	use super::*;
+
+	#[test]
+	fn targets() {
+		assert_eq!(
+			target(Path::new("index.tcat")),
+			Target::Page("index.html".into())
+		);
+		assert_eq!(
+			target(Path::new("blog/post.tcat")),
+			Target::Page("blog/post.html".into())
+		);
+		assert_eq!(
+			target(Path::new("img/cat.png")),
+			Target::Asset("img/cat.png".into())
+		);
+
+		// Partials can be within directories, or be within a directory starting with '_'
+		assert_eq!(target(Path::new("_nav.tcat")), Target::Partial);
+		assert_eq!(target(Path::new("blog/_nav.tcat")), Target::Partial);
+		assert_eq!(target(Path::new("_partials/nav.tcat")), Target::Partial);
+		assert_eq!(target(Path::new("blog/_drafts/post.tcat")), Target::Partial);
+
+		// Only TeaCat files are partials
+		assert_eq!(
+			target(Path::new("_assets/cat.png")),
+			Target::Asset("_assets/cat.png".into())
+		);
+		assert_eq!(
+			target(Path::new("blog_2024/post.tcat")),
+			Target::Page("blog_2024/post.html".into())
+		);
+	}
+
+	#[test]
+	fn skips_out_dir() {
+		let temp = TempDir::new("build_entries");
+		let src = &temp.0;
+		for file in [
+			"index.tcat",
+			"blog/post.tcat",
+			"dist/index.html",
+			"drafts/x.tcat",
+		] {
+			let path = src.join(file);
+			fs::create_dir_all(path.parent().unwrap()).unwrap();
+			fs::write(path, "").unwrap();
+		}
+
+		let config = Config {
+			ignore: vec![glob::Pattern::new("drafts").unwrap()],
+			..Config::default()
+		};
+		// The output directory is found even when written differently
+		let out_dir = src.join("blog/../dist");
+		let paths: Vec<PathBuf> = entries(src, Some(&out_dir), &config)
+			.map(|entry| entry.unwrap().path().strip_prefix(src).unwrap().into())
+			.collect();
+		assert_eq!(
+			paths,
+			[
+				PathBuf::new(),
+				"blog".into(),
+				"blog/post.tcat".into(),
+				"index.tcat".into()
+			]
+		);
+		assert_eq!(entries(src, None, &config).count(), 6);
+	}
+}
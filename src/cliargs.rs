@@ -1,6 +1,11 @@
-use std::path::PathBuf;
+use std::{env, ffi::OsString, path::PathBuf};
 
-use clap::{ArgMatches, ValueEnum, arg, builder::PossibleValue, value_parser};
+use clap::{
+	Arg, ArgAction, ArgMatches, ColorChoice, Command, ValueEnum, arg, builder::PossibleValue,
+	value_parser,
+};
+
+use crate::config::CONFIG_NAME;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RendererArg {
@@ -22,22 +27,365 @@ impl ValueEnum for RendererArg {
 	}
 }
 
+/// How errors and warnings are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormatArg {
+	Human,
+	Json,
+}
+
+impl ValueEnum for MessageFormatArg {
+	fn value_variants<'a>() -> &'a [Self] {
+		&[Self::Human, Self::Json]
+	}
+
+	fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+		Some(match self {
+			Self::Human => PossibleValue::new("human"),
+			Self::Json => PossibleValue::new("json"),
+		})
+	}
+}
+
+/// Parses the command line arguments. If no subcommand is given, `render` is used, so that
+/// `teacat index.tcat` is the same as `teacat render index.tcat`.
+///
+/// The subcommand is always moved before any global options, as clap replaces the values of
+/// a global option given before a subcommand with any given after it, which would lose some
+/// uses of `--define`.
 pub fn args() -> ArgMatches {
+	let command = command();
+	let args = rewrite(&command, env::args_os().collect());
+	command.get_matches_from(args)
+}
+
+/// Rewrites the arguments (including the name of the program) as described in [args], by
+/// moving the subcommand to the start or inserting `render` if there is none.
+fn rewrite(command: &Command, mut args: Vec<OsString>) -> Vec<OsString> {
+	if args.is_empty() {
+		return args;
+	}
+
+	let globals = leading_globals(command, &args[1..]);
+	let next = args
+		.get(1 + globals)
+		.map(|arg| arg.to_string_lossy().into_owned());
+
+	match next {
+		Some(next) if is_info(&next) => (),
+		Some(next) if command.find_subcommand(&next).is_some() => {
+			let subcommand = args.remove(1 + globals);
+			args.insert(1, subcommand);
+		}
+		_ => args.insert(1, "render".into()),
+	}
+
+	args
+}
+
+/// Counts the global options (and their values) at the start of the arguments, excluding the
+/// name of the program.
+fn leading_globals(command: &Command, args: &[OsString]) -> usize {
+	let mut count = 0;
+
+	while let Some(arg) = args.get(count).map(|arg| arg.to_string_lossy()) {
+		let name = arg.split('=').next().unwrap_or_default();
+		let global = command.get_arguments().find(|global| {
+			global.is_global_set()
+				&& global
+					.get_long()
+					.is_some_and(|long| name == format!("--{long}"))
+		});
+
+		match global {
+			Some(global) if global.get_action().takes_values() && !arg.contains('=') => count += 2,
+			Some(_) => count += 1,
+			None => break,
+		}
+	}
+
+	count.min(args.len())
+}
+
+/// Whether an argument asks for information (such as `--help`) rather than running a
+/// subcommand.
+fn is_info(arg: &str) -> bool {
+	let info = ["help", "-h", "--help", "-V", "--version", "--explain"];
+	info.contains(&arg.split('=').next().unwrap_or_default())
+}
+
+fn command() -> Command {
 	clap::command!()
-		.arg(arg!([file] "The file to read").value_parser(value_parser!(PathBuf)))
+		.subcommand_value_name("COMMAND")
+		.after_help(format!("If no command is given, 'render' is used, so 'teacat index.tcat' is the same as 'teacat render index.tcat'.\n\nOptions for a project can be set in a '{CONFIG_NAME}' file, within the current directory or any directory above it. Options given on the command line are used instead of these.\n\nEnvironment variables can be used within files as variables, such as '&env.HOME'. An 'env' list in '{CONFIG_NAME}' limits which can be used, and 'serve' can only use those listed.\n\nFiles can start with front matter written in TOML between two '+++' lines, whose entries (such as 'title') are defined as variables within the file instead of being rendered."))
 		.arg(
-			arg!(-o --out <FILE> "The file to output to")
+			arg!(--color <WHEN> "When to use colours in errors (also disabled by the NO_COLOR variable)")
+				.global(true)
 				.required(false)
-				.value_parser(value_parser!(PathBuf)),
+				.default_value("auto")
+				.value_parser(value_parser!(ColorChoice)),
+		)
+		.arg(
+			arg!(--message_format <FORMAT> "How errors are printed, with 'json' printing one JSON object per line")
+				.id("message_format")
+				.long("message-format")
+				.global(true)
+				.required(false)
+				.default_value("human")
+				.value_parser(value_parser!(MessageFormatArg)),
 		)
 		.arg(
-			arg!(-r --renderer <RENDERER> "The renderer to use for the outputted file")
+			arg!(--context <LINES> "The number of lines shown before and after each line that errors point at")
+				.global(true)
 				.required(false)
-				.value_parser(value_parser!(RendererArg)),
+				.default_value("1")
+				.value_parser(value_parser!(usize)),
 		)
 		.arg(
-			arg!(--stress_test "Runs the program several times to test performance")
+			arg!(--deny_warnings "Fails if any warnings are found")
+				.id("deny_warnings")
+				.long("deny-warnings")
+				.global(true)
 				.required(false),
 		)
-		.get_matches()
+		.arg(
+			arg!(--pretty "Indents the outputted HTML (also set by 'pretty' in the config)")
+				.global(true)
+				.required(false)
+				.conflicts_with("minify"),
+		)
+		.arg(
+			arg!(--minify "Writes the outputted HTML without extra whitespace, even if 'pretty' is set in the config")
+				.global(true)
+				.required(false),
+		)
+		.arg(
+			arg!(--define <DEFINITION> "Defines a variable within every file, such as 'version=1.0' for '&version' (can be used more than once)")
+				.value_name("NAME=VALUE")
+				.global(true)
+				.required(false)
+				.action(ArgAction::Append)
+				.value_parser(definition),
+		)
+		.arg(
+			arg!(--data <FILE> "Loads a JSON or TOML file as variables within every file, named after the file (can be used more than once)")
+				.global(true)
+				.required(false)
+				.action(ArgAction::Append)
+				.value_parser(value_parser!(PathBuf)),
+		)
+		.arg(arg!(--explain <CODE> "Explains an error or warning code, such as TC0001").required(false))
+		.subcommand(
+			Command::new("render")
+				.about("Renders a TeaCat file, printing the result or writing it to a file")
+				.arg(file_arg())
+				.arg(
+					arg!(-o --out <FILE> "The file to output to, or '-' (the default) to write to stdout")
+						.required(false)
+						.value_parser(value_parser!(PathBuf)),
+				)
+				.arg(
+					arg!(-r --renderer <RENDERER> "The renderer to use for the outputted file (defaults to 'renderer' in the config, or 'html')")
+						.required(false)
+						.value_parser(value_parser!(RendererArg)),
+				)
+				.arg(
+					arg!(--stress_test "Renders the file several times to test performance")
+						.id("stress_test")
+						.long("stress-test")
+						.alias("stress_test")
+						.required(false)
+						.hide(true),
+				),
+		)
+		.subcommand(
+			Command::new("check")
+				.about("Checks TeaCat files for errors without rendering them")
+				.arg(
+					arg!([paths] ... "The files or directories to check, or '-' to read from stdin (defaults to 'src' in the config, or '-')")
+						.value_parser(value_parser!(PathBuf)),
+				)
+				.after_help("Directories are checked in the same way as 'build', so partials are skipped."),
+		)
+		.subcommand(
+			Command::new("fmt")
+				.about("Formats TeaCat files in place")
+				.arg(
+					arg!(<files> ... "The files to format, with '-' formatting stdin to stdout")
+						.value_parser(value_parser!(PathBuf)),
+				)
+				.arg(
+					arg!(--check "Checks if the files are formatted without changing them")
+						.required(false),
+				),
+		)
+		.subcommand(
+			Command::new("build")
+				.about("Renders every TeaCat file within a directory into HTML, copying any other files")
+				.after_help("TeaCat files starting with '_' (or within a directory starting with '_') are partials, and are not rendered.")
+				.arg(arg!([dir] "The directory to read (defaults to 'src' in the config)").value_parser(value_parser!(PathBuf)))
+				.arg(
+					arg!(-o --out_dir <DIR> "The directory to output to (defaults to 'out' in the config, or 'dist')")
+						.id("out_dir")
+						.long("out-dir")
+						.required(false)
+						.value_parser(value_parser!(PathBuf)),
+				),
+		)
+		.subcommand(
+			Command::new("watch")
+				.about("Renders a TeaCat file (or directory, like 'build') into HTML whenever it changes")
+				.arg(arg!([input] "The file or directory to read (defaults to 'src' in the config)").value_parser(value_parser!(PathBuf)))
+				.arg(
+					arg!(-o --out <PATH> "The file or directory to output to (defaults to the file with an 'html' extension, or 'out' in the config, or 'dist')")
+						.required(false)
+						.value_parser(value_parser!(PathBuf)),
+				),
+		)
+		.subcommand(
+			Command::new("serve")
+				.about("Serves a directory on localhost, rendering TeaCat files when requested and reloading pages when files change")
+				.arg(
+					arg!([dir] "The directory to serve (defaults to 'src' in the config, or the current directory)")
+						.value_parser(value_parser!(PathBuf)),
+				)
+				.arg(
+					arg!(-p --port <PORT> "The port to serve on")
+						.required(false)
+						.default_value("8080")
+						.value_parser(value_parser!(u16)),
+				),
+		)
+		.subcommand(
+			Command::new("convert")
+				.about("Converts an HTML file into TeaCat")
+				.arg(
+					arg!(<file> "The HTML file to read, or '-' to read from stdin")
+						.value_parser(value_parser!(PathBuf)),
+				)
+				.arg(
+					arg!(-o --out <FILE> "The file to output to, or '-' (the default) to write to stdout")
+						.required(false)
+						.value_parser(value_parser!(PathBuf)),
+				),
+		)
+		.subcommand(
+			Command::new("tokens")
+				.about("Prints the tokens of a TeaCat file alongside their lines and columns, for debugging")
+				.arg(file_arg()),
+		)
+		.subcommand(
+			Command::new("ast")
+				.about("Prints the syntax tree of a TeaCat file, for debugging")
+				.arg(file_arg()),
+		)
+		.subcommand(
+			Command::new("expanded")
+				.about("Prints the syntax tree of a TeaCat file once variables and macros have been expanded, for debugging")
+				.arg(file_arg()),
+		)
+}
+
+/// Parses a variable given with `--define`, as its name and value.
+fn definition(arg: &str) -> Result<(String, String), String> {
+	match arg.split_once('=') {
+		Some((name, value)) if !name.is_empty() && !name.contains(char::is_whitespace) => {
+			Ok((name.into(), value.into()))
+		}
+		Some(_) => Err(format!("'{arg}' does not start with a valid variable name")),
+		None => Err(format!("expected 'name=value', found '{arg}'")),
+	}
+}
+
+fn file_arg() -> Arg {
+	arg!([file] "The file to read, or '-' (the default) to read from stdin")
+		.default_value("-")
+		.value_parser(value_parser!(PathBuf))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn rewritten(args: &str) -> Vec<String> {
+		let args = args.split(' ').map(OsString::from).collect();
+		rewrite(&command(), args)
+			.into_iter()
+			.map(|arg| arg.into_string().unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn subcommands() {
+		assert_eq!(
+			rewritten("teacat index.tcat"),
+			["teacat", "render", "index.tcat"]
+		);
+		assert_eq!(rewritten("teacat"), ["teacat", "render"]);
+		assert_eq!(
+			rewritten("teacat --color never index.tcat"),
+			["teacat", "render", "--color", "never", "index.tcat"]
+		);
+		assert_eq!(
+			rewritten("teacat --color=never build"),
+			["teacat", "build", "--color=never"]
+		);
+		assert_eq!(rewritten("teacat build"), ["teacat", "build"]);
+
+		// Asking for information is left to clap
+		assert_eq!(
+			rewritten("teacat --explain TC0001"),
+			["teacat", "--explain", "TC0001"]
+		);
+		assert_eq!(
+			rewritten("teacat --color never --help"),
+			["teacat", "--color", "never", "--help"]
+		);
+	}
+
+	#[test]
+	fn defines() {
+		let defines = |args: &str| {
+			let args = rewrite(&command(), args.split(' ').map(OsString::from).collect());
+			let matches = command().try_get_matches_from(args).unwrap();
+			let defines: Vec<(String, String)> = matches
+				.get_many::<(String, String)>("define")
+				.into_iter()
+				.flatten()
+				.cloned()
+				.collect();
+			(matches.subcommand_name().map(str::to_string), defines)
+		};
+		let define = |name: &str, value: &str| (name.to_string(), value.to_string());
+
+		// Definitions before and after the subcommand are all kept
+		assert_eq!(
+			defines("teacat --define a=1 build --define b=2"),
+			(
+				Some("build".into()),
+				vec![define("a", "1"), define("b", "2")]
+			)
+		);
+		assert_eq!(
+			defines("teacat --define=a=1 index.tcat"),
+			(Some("render".into()), vec![define("a", "1")])
+		);
+		assert_eq!(
+			defines("teacat render index.tcat --define a=1"),
+			(Some("render".into()), vec![define("a", "1")])
+		);
+	}
+
+	#[test]
+	fn definitions() {
+		assert_eq!(definition("a=b"), Ok(("a".into(), "b".into())));
+		assert_eq!(definition("a="), Ok(("a".into(), "".into())));
+		// Only the first '=' separates the name from the value
+		assert_eq!(definition("a=b=c"), Ok(("a".into(), "b=c".into())));
+
+		assert!(definition("=b").is_err());
+		assert!(definition("a b=c").is_err());
+		assert!(definition("a").is_err());
+		assert!(definition("").is_err());
+	}
 }
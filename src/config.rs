@@ -0,0 +1,263 @@
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	env, fs,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{Result as CatResult, anyhow};
+use clap::ValueEnum;
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Deserializer, de::Error};
+use teacat_lib::{
+	data::{data_vars, read_data},
+	prelude::*,
+};
+
+use crate::cliargs::RendererArg;
+
+/// The name of the file that configures a project.
+pub const CONFIG_NAME: &str = "teacat.toml";
+
+/// The settings for a project, read from its `teacat.toml`. Anything given on the command line
+/// is used instead of these.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	/// The directory that is read if no input is given.
+	pub src: Option<PathBuf>,
+	/// The directory that is written to if no output is given.
+	pub out: Option<PathBuf>,
+	/// The renderer used by `render` if none is given.
+	#[serde(deserialize_with = "renderer")]
+	pub renderer: Option<RendererArg>,
+	/// Whether HTML is indented, rather than being written without extra whitespace.
+	pub pretty: bool,
+	/// Whether HTML is written without extra whitespace, which is the default unless `pretty`
+	/// is set. Both cannot be set at once.
+	pub minify: bool,
+	/// Variables that are defined within every file, as text. Any given with `--define` are
+	/// added to these.
+	pub vars: BTreeMap<String, String>,
+	/// Files and directories (such as `drafts` or `*.bak`) within the source directory that
+	/// are skipped.
+	#[serde(deserialize_with = "patterns")]
+	pub ignore: Vec<Pattern>,
+	/// Directories that data files loaded with `@data` are found within, after the directory
+	/// of the file being evaluated.
+	pub include: Vec<PathBuf>,
+	/// The most macros that can be expanded within each other, if not the default of
+	/// [ExpandOptions::max_depth].
+	pub max_depth: Option<usize>,
+	/// The environment variables that can be used within files (such as `HOME` for
+	/// `&env.HOME`). If this is not set, all of them can be used, except by `serve`.
+	pub env: Option<BTreeSet<String>>,
+	/// Variables from the data files given with `--data`.
+	#[serde(skip)]
+	pub data: BTreeMap<String, ExpandedAst>,
+	/// The data files given with `--data`.
+	#[serde(skip)]
+	pub data_files: Vec<PathBuf>,
+	/// The file that the config was read from, if there is one.
+	#[serde(skip)]
+	pub file: Option<PathBuf>,
+}
+
+impl Config {
+	/// Reads the `teacat.toml` within the current directory, or the closest directory above it.
+	/// If there is none, the default config is used.
+	pub fn find() -> CatResult<Self> {
+		let current = env::current_dir().map_err(|err| TeaCatError::io(".", err))?;
+
+		match current
+			.ancestors()
+			.map(|dir| dir.join(CONFIG_NAME))
+			.find(|file| file.is_file())
+		{
+			Some(file) => Self::read(file.strip_prefix(&current).unwrap_or(&file)),
+			None => Ok(Self::default()),
+		}
+	}
+
+	/// Reads a config file. Paths within it are relative to the directory it is within.
+	pub fn read(file: &Path) -> CatResult<Self> {
+		let str = fs::read_to_string(file).map_err(|err| TeaCatError::io(file, err))?;
+		let mut config: Self = toml::from_str(&str).map_err(|err| {
+			anyhow!(
+				"could not read '{}': {}",
+				file.display(),
+				err.to_string().trim_end()
+			)
+		})?;
+
+		if config.pretty && config.minify {
+			return Err(anyhow!(
+				"could not read '{}': 'pretty' and 'minify' cannot both be set",
+				file.display()
+			));
+		}
+
+		let root = file.parent().unwrap_or(Path::new(""));
+		config.src = config.src.map(|src| root.join(src));
+		config.out = config.out.map(|out| root.join(out));
+		config.include = config.include.iter().map(|dir| root.join(dir)).collect();
+		config.file = Some(file.to_path_buf());
+
+		Ok(config)
+	}
+
+	/// Loads a data file, defining its variables (named after the file) within every file.
+	pub fn load_data(&mut self, file: &Path) -> CatResult<()> {
+		let value = read_data(file).map_err(|err| anyhow!("could not load data: {err}"))?;
+		let name = file.file_stem().unwrap_or_default().to_string_lossy();
+
+		self.data.extend(data_vars(&name, &value));
+		self.data_files.push(file.to_path_buf());
+		Ok(())
+	}
+
+	/// The directory that is written to if no output is given, which is `dist` by default.
+	pub fn out_dir(&self) -> &Path {
+		self.out.as_deref().unwrap_or(Path::new("dist"))
+	}
+
+	/// The [ExpandOptions] used when evaluating a file, which define the variables from the
+	/// config and data files, allow the environment variables within [Config::env], and limit
+	/// how deeply macros are nested to [Config::max_depth].
+	pub fn expand_options(&self, file: &Path) -> ExpandOptions {
+		let mut vars = self.data.clone();
+		vars.extend(
+			self.vars
+				.iter()
+				.map(|(name, text)| (name.clone(), ExpandedAst::text(text))),
+		);
+
+		let dir = file.parent().unwrap_or(Path::new(""));
+		ExpandOptions {
+			max_depth: self.max_depth.unwrap_or(ExpandOptions::default().max_depth),
+			vars,
+			data_dirs: [dir.to_path_buf()]
+				.into_iter()
+				.chain(self.include.iter().cloned())
+				.collect(),
+			env: match &self.env {
+				Some(names) => EnvAccess::Only(names.clone()),
+				None => EnvAccess::All,
+			},
+		}
+	}
+
+	/// Whether a path within the source directory (or any directory it is within) is ignored.
+	/// Patterns without a `/` are matched against names, so `*.bak` ignores backups within
+	/// every directory.
+	pub fn is_ignored(&self, relative: &Path) -> bool {
+		let options = MatchOptions {
+			require_literal_separator: true,
+			..MatchOptions::new()
+		};
+
+		relative
+			.ancestors()
+			.filter(|path| !path.as_os_str().is_empty())
+			.any(|path| {
+				self.ignore.iter().any(|pattern| {
+					if pattern.as_str().contains('/') {
+						pattern.matches_path_with(path, options)
+					} else {
+						path.file_name().is_some_and(|name| {
+							pattern.matches_with(&name.to_string_lossy(), options)
+						})
+					}
+				})
+			})
+	}
+}
+
+fn renderer<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<RendererArg>, D::Error> {
+	let name = String::deserialize(deserializer)?;
+	RendererArg::from_str(&name, true)
+		.map(Some)
+		.map_err(|_| D::Error::custom(format!("unknown renderer '{name}'")))
+}
+
+fn patterns<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Pattern>, D::Error> {
+	Vec::<String>::deserialize(deserializer)?
+		.iter()
+		.map(|pattern| {
+			Pattern::new(pattern)
+				.map_err(|err| D::Error::custom(format!("invalid pattern '{pattern}': {err}")))
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use teacat_lib::testing::TempDir;
+
+	use super::*;
+
+	fn ignoring(patterns: &[&str]) -> Config {
+		Config {
+			ignore: patterns
+				.iter()
+				.map(|pattern| Pattern::new(pattern).unwrap())
+				.collect(),
+			..Config::default()
+		}
+	}
+
+	#[test]
+	fn ignored() {
+		// Patterns without a '/' match the name of the path, or of any directory it is within
+		let config = ignoring(&["*.bak", "drafts"]);
+		assert!(config.is_ignored(Path::new("index.tcat.bak")));
+		assert!(config.is_ignored(Path::new("blog/old.bak")));
+		assert!(config.is_ignored(Path::new("drafts")));
+		assert!(config.is_ignored(Path::new("drafts/post.tcat")));
+		assert!(config.is_ignored(Path::new("blog/drafts/post.tcat")));
+		assert!(!config.is_ignored(Path::new("index.tcat")));
+		assert!(!config.is_ignored(Path::new("drafts.tcat")));
+
+		// Patterns with a '/' match the whole path from the source directory
+		let config = ignoring(&["blog/*.tcat"]);
+		assert!(config.is_ignored(Path::new("blog/post.tcat")));
+		assert!(config.is_ignored(Path::new("blog/post.tcat/image.png")));
+		assert!(!config.is_ignored(Path::new("post.tcat")));
+		assert!(!config.is_ignored(Path::new("blog/2024/post.tcat")));
+		assert!(!config.is_ignored(Path::new("old/blog/post.tcat")));
+	}
+
+	#[test]
+	fn read() {
+		let temp = TempDir::new("config_read");
+		let dir = &temp.0;
+		let file = dir.join(CONFIG_NAME);
+		fs::write(
+			&file,
+			"src = 'pages'\nout = '../dist'\ninclude = ['data']\nenv = ['HOME']\npretty = true\nmax_depth = 8",
+		)
+		.unwrap();
+
+		// Paths are relative to the directory of the config
+		let config = Config::read(&file).unwrap();
+		assert_eq!(config.src, Some(dir.join("pages")));
+		assert_eq!(config.out_dir(), dir.join("../dist"));
+		assert_eq!(config.include, [dir.join("data")]);
+		assert!(config.pretty);
+
+		let options = config.expand_options(&dir.join("pages/index.tcat"));
+		assert_eq!(options.data_dirs, [dir.join("pages"), dir.join("data")]);
+		assert_eq!(options.max_depth, 8);
+		assert_eq!(
+			options.env,
+			EnvAccess::Only(BTreeSet::from(["HOME".into()]))
+		);
+		let options = Config::default().expand_options(Path::new("index.tcat"));
+		assert_eq!(options.env, EnvAccess::All);
+		assert_eq!(options.max_depth, ExpandOptions::default().max_depth);
+
+		fs::write(&file, "pretty = true\nminify = true").unwrap();
+		assert!(Config::read(&file).is_err());
+		fs::write(&file, "unknown = true").unwrap();
+		assert!(Config::read(&file).is_err());
+	}
+}
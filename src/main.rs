@@ -1,74 +1,329 @@
 #![allow(clippy::tabs_in_doc_comments)]
 
-use std::{fs, path::PathBuf, process::ExitCode};
+use std::{
+	fs,
+	io::{self, Read},
+	path::{Path, PathBuf},
+	process::ExitCode,
+};
 
-use anstyle::{AnsiColor, Color, Style};
 use anyhow::{Result as CatResult, anyhow};
-use cliargs::RendererArg;
-use teacat_lib::prelude::*;
+use clap::{ArgMatches, ColorChoice};
+use cliargs::{MessageFormatArg, RendererArg};
+use config::{CONFIG_NAME, Config};
+use report::Reporter;
+use teacat_lib::{
+	error::codes,
+	front_matter::{metadata_vars, parse_front_matter},
+	prelude::*,
+};
 
+mod build;
 mod cliargs;
+mod config;
+mod report;
+mod serve;
+mod watch;
 
-const ERR: Style = colorstyle(AnsiColor::Red);
-const DEFAULT: Style = colorstyle(AnsiColor::White);
-const BOLD: Style = Style::new().bold();
+/// The path used to read from stdin, or to write to stdout.
+const STD_PATH: &str = "-";
+/// The name shown in errors for TeaCat read from stdin.
+const STDIN_NAME: &str = "<stdin>";
 
 fn main() -> ExitCode {
-	match teacat() {
+	let args = cliargs::args();
+
+	match args.get_one::<ColorChoice>("color") {
+		Some(ColorChoice::Always) => anstream::ColorChoice::Always,
+		Some(ColorChoice::Never) => anstream::ColorChoice::Never,
+		_ => anstream::ColorChoice::Auto,
+	}
+	.write_global();
+
+	let reporter = Reporter {
+		format: args
+			.get_one::<MessageFormatArg>("message_format")
+			.copied()
+			.unwrap_or(MessageFormatArg::Human),
+		deny_warnings: args.get_flag("deny_warnings"),
+		context: *args
+			.get_one::<usize>("context")
+			.expect("context has a default"),
+	};
+
+	match teacat(&args, &reporter) {
 		Ok(_) => ExitCode::SUCCESS,
 		Err(e) => {
-			eprintln!("\n{BOLD}{ERR}Error{DEFAULT}: {e}{BOLD:#}");
+			reporter.error(&e);
 			ExitCode::FAILURE
 		}
 	}
 }
 
-fn teacat() -> CatResult<()> {
-	let args = cliargs::args();
+fn teacat(args: &ArgMatches, reporter: &Reporter) -> CatResult<()> {
+	if let Some(code) = args.get_one::<String>("explain") {
+		let Some(explanation) = codes::explain(code) else {
+			return Err(anyhow!("'{code}' is not a TeaCat error code"));
+		};
+		print!("{explanation}");
+		return Ok(());
+	}
 
-	let Some(file) = args.try_get_one::<PathBuf>("file")? else {
-		return Err(anyhow!("no file provided"));
+	let config = load_config(args)?;
+
+	let file = |args: &ArgMatches| {
+		args.get_one::<PathBuf>("file")
+			.expect("file has a default")
+			.clone()
 	};
-	let out = args.try_get_one::<PathBuf>("out")?;
 
-	let fun = match args.try_get_one::<RendererArg>("renderer")? {
+	match args.subcommand() {
+		Some(("render", args)) => render(args, &config, reporter),
+		Some(("check", args)) => {
+			let mut paths: Vec<&Path> = args
+				.get_many::<PathBuf>("paths")
+				.into_iter()
+				.flatten()
+				.map(PathBuf::as_path)
+				.collect();
+			if paths.is_empty() {
+				paths.push(config.src.as_deref().unwrap_or(Path::new(STD_PATH)));
+			}
+			build::check(&paths, &config, reporter)
+		}
+		Some(("fmt", args)) => fmt(args, reporter),
+		Some(("build", args)) => {
+			let dir = src(args, "dir", &config)?;
+			let out_dir = args
+				.get_one::<PathBuf>("out_dir")
+				.map_or(config.out_dir(), PathBuf::as_path);
+			build::build(dir, out_dir, &config, reporter)
+		}
+		Some(("watch", args)) => {
+			let input = src(args, "input", &config)?;
+			let out = match args.get_one::<PathBuf>("out") {
+				Some(out) => out.clone(),
+				None if input.is_dir() => config.out_dir().into(),
+				None => input.with_extension("html"),
+			};
+			watch::watch(input, &out, &config, || load_config(args), reporter)
+		}
+		Some(("serve", args)) => {
+			let dir = src(args, "dir", &config).unwrap_or(Path::new("."));
+			let port = args.get_one::<u16>("port").expect("port has a default");
+			serve::serve(dir, *port, &config, reporter)
+		}
+		Some(("convert", args)) => convert(args),
+		Some(("tokens", args)) => dump(Stage::Tokens, &file(args), &config, reporter),
+		Some(("ast", args)) => dump(Stage::Ast, &file(args), &config, reporter),
+		Some(("expanded", args)) => dump(Stage::Expanded, &file(args), &config, reporter),
+		_ => unreachable!("'render' is used if no subcommand is given"),
+	}
+}
+
+/// Finds the [Config] (or uses the default one), with anything given on the command line
+/// added to it.
+fn load_config(args: &ArgMatches) -> CatResult<Config> {
+	let mut config = Config::find()?;
+	// Either flag is used instead of the config
+	if args.get_flag("pretty") || args.get_flag("minify") {
+		config.pretty = args.get_flag("pretty");
+		config.minify = args.get_flag("minify");
+	}
+	config.vars.extend(
+		args.get_many::<(String, String)>("define")
+			.into_iter()
+			.flatten()
+			.cloned(),
+	);
+	for file in args.get_many::<PathBuf>("data").into_iter().flatten() {
+		config.load_data(file)?;
+	}
+
+	Ok(config)
+}
+
+/// Gets the directory given on the command line, or the source directory from the [Config] if
+/// there is none.
+fn src<'a>(args: &'a ArgMatches, id: &str, config: &'a Config) -> CatResult<&'a Path> {
+	args.get_one::<PathBuf>(id)
+		.or(config.src.as_ref())
+		.map(PathBuf::as_path)
+		.ok_or_else(|| anyhow!("no <{id}> was given, and there is no 'src' set in {CONFIG_NAME}"))
+}
+
+fn render(args: &ArgMatches, config: &Config, reporter: &Reporter) -> CatResult<()> {
+	let file = args.get_one::<PathBuf>("file").expect("file has a default");
+	let out = args.get_one::<PathBuf>("out").map(PathBuf::as_path);
+
+	let renderer = args
+		.get_one::<RendererArg>("renderer")
+		.or(config.renderer.as_ref());
+	let fun = match renderer {
 		Some(RendererArg::TeaCat) => run::<TeaCatRenderer>,
+		_ if config.pretty => run::<PrettyHtmlRenderer>,
 		_ => run::<HtmlRenderer>,
 	};
 
 	if args.get_flag("stress_test") {
 		for _ in 0..10000 {
-			fun(file, out)?;
+			fun(file, out, config, reporter)?;
 		}
 	}
 
-	fun(file, out)
+	fun(file, out, config, reporter)
 }
 
-fn run<R: Renderer<String>>(file: &PathBuf, out: Option<&PathBuf>) -> CatResult<()> {
-	let str = fs::read_to_string(file)?;
-	let html = eval::<R>(str)?;
+fn run<R: Renderer<String>>(
+	file: &Path,
+	out: Option<&Path>,
+	config: &Config,
+	reporter: &Reporter,
+) -> CatResult<()> {
+	let html = eval::<R>(file, config, reporter)?;
+	write(out, &html)
+}
 
-	if let Some(file) = out {
-		fs::write(file, html)?;
+/// Reads and evaluates a TeaCat file (or stdin) into HTML, which is indented if
+/// [Config::pretty] is set.
+fn eval_html(file: &Path, config: &Config, reporter: &Reporter) -> CatResult<String> {
+	if config.pretty {
+		eval::<PrettyHtmlRenderer>(file, config, reporter)
 	} else {
-		println!("{html}");
+		eval::<HtmlRenderer>(file, config, reporter)
 	}
+}
+
+/// Reads and evaluates a TeaCat file (or stdin), reporting any errors or warnings.
+fn eval<R: Renderer<String>>(
+	file: &Path,
+	config: &Config,
+	reporter: &Reporter,
+) -> CatResult<String> {
+	let (name, str) = read(file)?;
+	let mut diagnostics = Diagnostics::new();
+	let result =
+		eval_teacat_string_with::<R, _>(&str, &config.expand_options(file), &mut diagnostics);
+	reporter.report(result, &diagnostics, name, &str)
+}
+
+/// How far through evaluating a file to go before printing the result, for debugging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+	Tokens,
+	Ast,
+	Expanded,
+}
 
+/// Prints the [TokenStream] (with lines and columns starting at one), [Ast] or [ExpandedAst]
+/// of a file.
+fn dump(stage: Stage, file: &Path, config: &Config, reporter: &Reporter) -> CatResult<()> {
+	let (name, str) = read(file)?;
+	let mut diagnostics = Diagnostics::new();
+
+	let result = parse_front_matter(&str).and_then(|(metadata, body)| {
+		let tokenstream = TokenStream::lex(body)?;
+		if stage == Stage::Tokens {
+			let lines: Vec<String> = tokenstream
+				.0
+				.iter()
+				.map(|(pos, token)| format!("{:>4}:{:<3} {token:?}", pos.line + 1, pos.col + 1))
+				.collect();
+			return Ok(lines.join("\n"));
+		}
+
+		let ast = Ast::parse_with_diagnostics(tokenstream, &mut diagnostics)?;
+		if stage == Stage::Ast {
+			return Ok(format!("{ast:#?}"));
+		}
+
+		let mut options = config.expand_options(file);
+		options.vars.extend(metadata_vars(&metadata));
+		let expanded = ExpandedAst::expand_with(ast, &options, &mut diagnostics)?;
+		Ok(format!("{expanded:#?}"))
+	});
+
+	println!("{}", reporter.report(result, &diagnostics, name, &str)?);
 	Ok(())
 }
 
-fn eval<R: Renderer<String>>(str: String) -> CatResult<String> {
-	match eval_teacat_string::<R, _>(&str) {
-		Err(err) => Err(if let Some(fancyerr) = err.downcast_ref::<TeaCatError>() {
-			anyhow!(fancyerr.err_fancy(str))
+/// Reads a file, or stdin if the path is `-`. Returns the name to show in errors alongside
+/// the contents.
+fn read(file: &Path) -> CatResult<(&Path, String)> {
+	if !is_std(file) {
+		let str = fs::read_to_string(file).map_err(|err| TeaCatError::io(file, err))?;
+		return Ok((file, str));
+	}
+
+	let name = Path::new(STDIN_NAME);
+	let mut str = String::new();
+	io::stdin()
+		.read_to_string(&mut str)
+		.map_err(|err| TeaCatError::io(name, err))?;
+	Ok((name, str))
+}
+
+/// Whether a path refers to stdin or stdout.
+fn is_std(path: &Path) -> bool {
+	path == Path::new(STD_PATH)
+}
+
+/// Writes to a file, or to stdout if there is no file (or the path is `-`).
+fn write(out: Option<&Path>, contents: &str) -> CatResult<()> {
+	match out {
+		Some(file) if !is_std(file) => {
+			fs::write(file, contents).map_err(|err| TeaCatError::io(file, err))?;
+		}
+		_ => println!("{contents}"),
+	}
+	Ok(())
+}
+
+/// Formats each file in place, in the order they were given. Stdin (`-`) is formatted to
+/// stdout instead, and can only be given once.
+fn fmt(args: &ArgMatches, reporter: &Reporter) -> CatResult<()> {
+	let check = args.get_flag("check");
+	let files: Vec<&PathBuf> = args
+		.get_many::<PathBuf>("files")
+		.into_iter()
+		.flatten()
+		.collect();
+	let mut unformatted = vec![];
+
+	if files.iter().filter(|file| is_std(file)).count() > 1 {
+		return Err(anyhow!("stdin ('{STD_PATH}') can only be formatted once"));
+	}
+
+	for file in files {
+		let (name, str) = read(file)?;
+		let formatted = reporter.report(format_str(&str), &Diagnostics::new(), name, &str)?;
+
+		if is_std(file) && !check {
+			print!("{formatted}");
+			continue;
+		}
+		if formatted == str {
+			continue;
+		}
+
+		if check {
+			unformatted.push(format!("\n  {}", name.display()));
 		} else {
-			err
-		}),
-		ok => ok,
+			fs::write(file, formatted).map_err(|err| TeaCatError::io(file, err))?;
+		}
+	}
+
+	if unformatted.is_empty() {
+		Ok(())
+	} else {
+		Err(anyhow!("files are not formatted:{}", unformatted.join("")))
 	}
 }
 
-const fn colorstyle(color: AnsiColor) -> Style {
-	Style::new().fg_color(Some(Color::Ansi(color)))
+fn convert(args: &ArgMatches) -> CatResult<()> {
+	let file = args.get_one::<PathBuf>("file").expect("file is required");
+	let (_, str) = read(file)?;
+	let tcat = TeaCatRenderer::render(ExpandedAst::from_html(str));
+
+	write(args.get_one::<PathBuf>("out").map(PathBuf::as_path), &tcat)
 }
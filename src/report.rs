@@ -0,0 +1,153 @@
+use std::{
+	error::Error,
+	fmt::{Display, Formatter},
+	path::Path,
+};
+
+use anstream::eprintln;
+use anstyle::{AnsiColor, Color, Style};
+use anyhow::{Result as CatResult, anyhow};
+use serde_json::{Value, json};
+use teacat_lib::prelude::*;
+
+use crate::cliargs::MessageFormatArg;
+
+const ERR: Style = colorstyle(AnsiColor::Red);
+const WARN: Style = colorstyle(AnsiColor::Yellow);
+const NOTE: Style = colorstyle(AnsiColor::Cyan);
+const DEFAULT: Style = colorstyle(AnsiColor::White);
+const BOLD: Style = Style::new().bold();
+
+/// Prints errors and warnings in the format chosen by the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reporter {
+	pub format: MessageFormatArg,
+	pub deny_warnings: bool,
+	/// The number of lines shown before and after each line that is pointed at.
+	pub context: usize,
+}
+
+/// An error that has already been printed, so only needs to stop the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reported;
+
+impl Reporter {
+	/// Prints the [Diagnostics] from evaluating a file, alongside its error (if there is one).
+	pub fn report<T>(
+		&self,
+		result: Result<T, TeaCatError>,
+		diagnostics: &Diagnostics,
+		file: &Path,
+		str: &str,
+	) -> CatResult<T> {
+		for diagnostic in diagnostics.iter() {
+			self.diagnostic(diagnostic, file, str);
+		}
+
+		let out = match (result, self.format) {
+			(Ok(out), _) => out,
+			(Err(err), MessageFormatArg::Human) => {
+				return Err(anyhow!(
+					"{}\nFor more information about this error, try 'teacat --explain {}'",
+					err.err_fancy_with(str, &self.options(file)),
+					err.code()
+				));
+			}
+			(Err(err), MessageFormatArg::Json) => {
+				self.diagnostic(&err.into(), file, str);
+				return Err(Reported.into());
+			}
+		};
+
+		let warnings = diagnostics.count(Severity::Warning);
+		if self.deny_warnings && warnings > 0 {
+			return Err(anyhow!(
+				"{warnings} warning(s) emitted, and warnings are denied"
+			));
+		}
+
+		Ok(out)
+	}
+
+	/// Prints a message about what the program is doing, unless the messages are being read by
+	/// another program.
+	pub fn status(&self, message: impl Display) {
+		if self.format == MessageFormatArg::Human {
+			eprintln!("{BOLD}{NOTE}Info{DEFAULT}: {message}{BOLD:#}");
+		}
+	}
+
+	/// Prints an error that ended the program (or a rebuild, when watching for changes).
+	pub fn error(&self, err: &anyhow::Error) {
+		if err.is::<Reported>() {
+			return;
+		}
+
+		match self.format {
+			MessageFormatArg::Human => eprintln!("\n{BOLD}{ERR}Error{DEFAULT}: {err}{BOLD:#}"),
+			MessageFormatArg::Json => eprintln!(
+				"{}",
+				json!({
+					"severity": Severity::Error.to_string(),
+					"message": err.to_string(),
+				})
+			),
+		}
+	}
+
+	fn diagnostic(&self, diagnostic: &Diagnostic, file: &Path, str: &str) {
+		match self.format {
+			MessageFormatArg::Human => {
+				let (style, label) = match diagnostic.severity() {
+					Severity::Error => (ERR, "Error"),
+					Severity::Warning => (WARN, "Warning"),
+					Severity::Note => (NOTE, "Note"),
+				};
+
+				eprintln!(
+					"\n{BOLD}{style}{label}{DEFAULT}: {}{BOLD:#}",
+					diagnostic.err_fancy_with(str, &self.options(file))
+				);
+			}
+			MessageFormatArg::Json => eprintln!("{}", json(diagnostic, file, str)),
+		}
+	}
+
+	/// The [ReportOptions] used when printing errors and warnings within a file.
+	pub fn options(&self, file: &Path) -> ReportOptions {
+		ReportOptions {
+			context: self.context,
+			name: Some(file.display().to_string()),
+			..ReportOptions::default()
+		}
+	}
+}
+
+impl Display for Reported {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.write_str("errors were reported")
+	}
+}
+
+impl Error for Reported {}
+
+/// Converts a [Diagnostic] into JSON. Lines and columns start at one, and `column_end` is the
+/// column after the end of the span.
+fn json(diagnostic: &Diagnostic, file: &Path, str: &str) -> Value {
+	let span = diagnostic.span(str);
+
+	json!({
+		"severity": diagnostic.severity().to_string(),
+		"code": diagnostic.code(),
+		"message": diagnostic.to_string(),
+		"help": diagnostic.help_msg(),
+		"file": file.display().to_string(),
+		"line": span.map(|span| span.line + 1),
+		"column_start": span.map(|span| span.start + 1),
+		"column_end": span.map(|span| span.end + 1),
+	})
+}
+
+const fn colorstyle(color: AnsiColor) -> Style {
+	Style::new().fg_color(Some(Color::Ansi(color)))
+}
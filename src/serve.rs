@@ -0,0 +1,356 @@
+use std::{
+	fs,
+	hash::{DefaultHasher, Hash, Hasher},
+	path::{Component, Path, PathBuf},
+	sync::{Arc, RwLock},
+	thread,
+};
+
+use anyhow::{Result as CatResult, anyhow};
+use html_escape::encode_text;
+use teacat_lib::prelude::*;
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::{build, config::Config, report::Reporter, watch};
+
+/// The URL that the live reload script checks for changes.
+const VERSION_URL: &str = "/__teacat/version";
+
+/// Reloads the page once the version of the files being served changes.
+const RELOAD_SCRIPT: &str = r#"<script>
+	setInterval(async () => {
+		const res = await fetch("{url}").catch(() => null);
+		if (res?.ok && (await res.text()) !== "{version}") location.reload();
+	}, 500);
+</script>"#;
+
+/// The version of the files being served, which is kept up to date by a background thread so
+/// that checking it is cheap.
+type Version = Arc<RwLock<String>>;
+
+/// Serves the files within a directory over HTTP, rendering TeaCat files when they are
+/// requested. Pages are reloaded in the browser whenever a file within the directory changes.
+pub fn serve(root: &Path, port: u16, config: &Config, reporter: &Reporter) -> CatResult<()> {
+	// Anyone who can reach the server can read the pages, so environment variables (which may
+	// contain secrets) can only be used if they are listed within the config
+	let config = &Config {
+		env: Some(config.env.clone().unwrap_or_default()),
+		..config.clone()
+	};
+
+	let server = Server::http(("127.0.0.1", port))
+		.map_err(|err| anyhow!("could not start the server on port {port}: {err}"))?;
+
+	reporter.status(format!(
+		"Serving '{}' at http://127.0.0.1:{port}",
+		root.display()
+	));
+
+	let version = watch_version(root, config);
+
+	for request in server.incoming_requests() {
+		let url = request.url().to_string();
+		let version = version
+			.read()
+			.expect("the version should always be set")
+			.clone();
+		if let Err(err) = respond(root, request, &version, config, reporter) {
+			reporter.error(&anyhow!("could not respond to '{url}': {err}"));
+		}
+	}
+
+	Ok(())
+}
+
+fn respond(
+	root: &Path,
+	request: Request,
+	version: &str,
+	config: &Config,
+	reporter: &Reporter,
+) -> CatResult<()> {
+	let path = request.url().split(['?', '#']).next().unwrap_or_default();
+
+	if path == VERSION_URL {
+		return Ok(request.respond(Response::from_string(version))?);
+	}
+
+	let Some(file) = resolve(root, path, config) else {
+		let response = Response::from_string(format!("'{path}' could not be found"))
+			.with_status_code(404)
+			.with_header(content_type("txt"));
+		return Ok(request.respond(response)?);
+	};
+
+	let ext = file.extension().unwrap_or_default().to_string_lossy();
+	let response = match ext.as_ref() {
+		"tcat" => {
+			let (status, html) = render(&file, version, config, reporter);
+			Response::from_string(html)
+				.with_status_code(status)
+				.with_header(content_type("html"))
+		}
+		"html" => {
+			let html = fs::read_to_string(&file).map_err(|err| TeaCatError::io(&file, err))?;
+			Response::from_string(live_reload(html, version)).with_header(content_type("html"))
+		}
+		_ => Response::from_data(fs::read(&file).map_err(|err| TeaCatError::io(&file, err))?)
+			.with_header(content_type(&ext)),
+	};
+
+	Ok(request.respond(response)?)
+}
+
+/// Finds the file that a URL path points to. Paths are checked in the same way as the output
+/// of `teacat build`, so `/about.html` can also be `about.tcat` (as can `/about`), and
+/// `/blog/` can be `blog/index.tcat`. Partials, ignored files and hidden files (such as
+/// `.env`) are never served.
+fn resolve(root: &Path, path: &str, config: &Config) -> Option<PathBuf> {
+	let path = percent_decode(path)?;
+	let relative = Path::new(path.trim_start_matches('/'));
+	if relative.components().any(|component| match component {
+		Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+		_ => true,
+	}) {
+		return None;
+	}
+
+	let candidates = if root.join(relative).is_dir() {
+		vec![relative.join("index.tcat"), relative.join("index.html")]
+	} else {
+		match relative.extension() {
+			Some(ext) if ext == "html" => vec![relative.with_extension("tcat"), relative.into()],
+			Some(_) => vec![relative.into()],
+			None => vec![
+				relative.with_extension("tcat"),
+				relative.with_extension("html"),
+			],
+		}
+	};
+
+	candidates
+		.into_iter()
+		.filter(|relative| !build::is_partial(relative) && !config.is_ignored(relative))
+		.map(|relative| root.join(relative))
+		.find(|file| file.is_file())
+}
+
+/// Decodes the escapes within a URL path (such as `%20` for a space), returning [None] if any
+/// are invalid or the result is not valid UTF-8.
+fn percent_decode(path: &str) -> Option<String> {
+	let mut bytes = path.bytes();
+	let mut decoded = vec![];
+
+	while let Some(byte) = bytes.next() {
+		if byte == b'%' {
+			let hex = [bytes.next()?, bytes.next()?];
+			decoded.push(u8::from_str_radix(str::from_utf8(&hex).ok()?, 16).ok()?);
+		} else {
+			decoded.push(byte);
+		}
+	}
+
+	String::from_utf8(decoded).ok()
+}
+
+/// Renders a TeaCat file into a page (with the live reload script), alongside the status code
+/// to respond with. Errors are shown on the page, as well as being reported.
+fn render(file: &Path, version: &str, config: &Config, reporter: &Reporter) -> (u16, String) {
+	let str = match fs::read_to_string(file) {
+		Ok(str) => str,
+		Err(err) => {
+			return (
+				500,
+				error_page(&TeaCatError::io(file, err), file, "", version, reporter),
+			);
+		}
+	};
+
+	let mut diagnostics = Diagnostics::new();
+	let options = config.expand_options(file);
+	let result = if config.pretty {
+		eval_teacat_string_with::<PrettyHtmlRenderer, String>(&str, &options, &mut diagnostics)
+	} else {
+		eval_teacat_string_with::<HtmlRenderer, String>(&str, &options, &mut diagnostics)
+	};
+
+	let page = match &result {
+		Ok(html) => (200, live_reload(html.clone(), version)),
+		Err(err) => (500, error_page(err, file, &str, version, reporter)),
+	};
+
+	if let Err(err) = reporter.report(result, &diagnostics, file, &str) {
+		reporter.error(&err);
+	}
+
+	page
+}
+
+/// Shows an error as a page, which reloads once the error may have been fixed.
+fn error_page(
+	err: &TeaCatError,
+	file: &Path,
+	str: &str,
+	version: &str,
+	reporter: &Reporter,
+) -> String {
+	let options = ReportOptions {
+		color: false,
+		..reporter.options(file)
+	};
+	let report = err.err_fancy_with(str, &options);
+
+	live_reload(
+		format!(
+			"<!DOCTYPE html><html><head><title>Error: {}</title></head><body><pre>{}</pre></body></html>",
+			encode_text(&err.to_string()),
+			encode_text(&report),
+		),
+		version,
+	)
+}
+
+/// Adds the live reload script to the end of a page.
+fn live_reload(mut html: String, version: &str) -> String {
+	let script = RELOAD_SCRIPT
+		.replace("{url}", VERSION_URL)
+		.replace("{version}", version);
+	let end = html
+		.rfind("</body>")
+		.or_else(|| html.rfind("</html>"))
+		.unwrap_or(html.len());
+
+	html.insert_str(end, &script);
+	html
+}
+
+/// Starts a background thread that updates the version whenever the files being served change,
+/// so that the files are only checked once per interval however many pages are open.
+fn watch_version(root: &Path, config: &Config) -> Version {
+	let version = Arc::new(RwLock::new(current_version(root, config)));
+	let (shared, root, config) = (Arc::clone(&version), root.to_path_buf(), config.clone());
+
+	thread::spawn(move || {
+		loop {
+			thread::sleep(watch::INTERVAL);
+			let current = current_version(&root, &config);
+			*shared.write().expect("the version should always be set") = current;
+		}
+	});
+
+	version
+}
+
+/// Gets a version for the files being served, which changes whenever any of them do.
+fn current_version(root: &Path, config: &Config) -> String {
+	let mut hasher = DefaultHasher::new();
+	watch::snapshot(root, None, config).hash(&mut hasher);
+	format!("{:x}", hasher.finish())
+}
+
+fn content_type(ext: &str) -> Header {
+	let mime = match ext {
+		"html" => "text/html; charset=utf-8",
+		"css" => "text/css; charset=utf-8",
+		"js" => "text/javascript; charset=utf-8",
+		"json" => "application/json",
+		"txt" => "text/plain; charset=utf-8",
+		"svg" => "image/svg+xml",
+		"png" => "image/png",
+		"jpg" | "jpeg" => "image/jpeg",
+		"gif" => "image/gif",
+		"webp" => "image/webp",
+		"ico" => "image/x-icon",
+		"woff2" => "font/woff2",
+		_ => "application/octet-stream",
+	};
+
+	Header::from_bytes("Content-Type", mime).expect("Content types should be valid headers")
+}
+
+#[cfg(test)]
+mod tests {
+	use glob::Pattern;
+	use teacat_lib::testing::TempDir;
+
+	use super::*;
+
+	#[test]
+	fn decoding() {
+		assert_eq!(percent_decode("/my%20page.html").unwrap(), "/my page.html");
+		assert_eq!(percent_decode("/caf%C3%A9").unwrap(), "/café");
+		assert_eq!(percent_decode("/a%2").as_deref(), None);
+		assert_eq!(percent_decode("/a%zz").as_deref(), None);
+		assert_eq!(percent_decode("/a%FF").as_deref(), None);
+	}
+
+	#[test]
+	fn resolving() {
+		let temp = TempDir::new("serve_resolve");
+		let root = &temp.0;
+		let files = [
+			"index.tcat",
+			"about.tcat",
+			"page.html",
+			"my page.html",
+			"blog/index.html",
+			"img/cat.png",
+			"_nav.tcat",
+			"drafts/post.tcat",
+			".env",
+			".git/config",
+		];
+		for file in files {
+			let path = root.join(file);
+			fs::create_dir_all(path.parent().unwrap()).unwrap();
+			fs::write(path, "").unwrap();
+		}
+
+		let config = Config {
+			ignore: vec![Pattern::new("drafts").unwrap()],
+			..Config::default()
+		};
+		let paths = [
+			"/",
+			"/about",
+			"/about.html",
+			"/page",
+			"/my%20page.html",
+			"/blog/",
+			"/img/cat.png",
+			"/missing.html",
+			"/_nav",
+			"/_nav.tcat",
+			"/drafts/post",
+			"/.env",
+			"/.git/config",
+			"/%2E%2E/index.tcat",
+			"/../index.tcat",
+		];
+		let resolved: Vec<_> = paths
+			.iter()
+			.map(|path| resolve(root, path, &config))
+			.map(|file| file.map(|file| file.strip_prefix(root).unwrap().to_path_buf()))
+			.collect();
+		assert_eq!(
+			resolved,
+			[
+				Some("index.tcat".into()),
+				Some("about.tcat".into()),
+				Some("about.tcat".into()),
+				Some("page.html".into()),
+				Some("my page.html".into()),
+				Some("blog/index.html".into()),
+				Some("img/cat.png".into()),
+				None,
+				None,
+				None,
+				None,
+				None,
+				None,
+				None,
+				None,
+			]
+		);
+	}
+}
@@ -0,0 +1,143 @@
+use std::{
+	collections::BTreeMap,
+	fs,
+	path::{Path, PathBuf},
+	thread,
+	time::{Duration, SystemTime},
+};
+
+use anyhow::Result as CatResult;
+use teacat_lib::data::DataFormat;
+use walkdir::DirEntry;
+
+use crate::{build, config::Config, eval_html, report::Reporter};
+
+/// How often the input is checked for changes.
+pub const INTERVAL: Duration = Duration::from_millis(250);
+
+/// The last time each file was modified, alongside its size.
+pub type Snapshot = BTreeMap<PathBuf, (SystemTime, u64)>;
+
+/// Builds the input (either a single TeaCat file or a directory) whenever it changes, until the
+/// program is stopped. The config is reloaded with `reload` before each rebuild, as it (or the
+/// data files it loads) may have changed. Errors are printed without stopping the program.
+pub fn watch(
+	input: &Path,
+	out: &Path,
+	config: &Config,
+	reload: impl Fn() -> CatResult<Config>,
+	reporter: &Reporter,
+) -> CatResult<()> {
+	reporter.status(format!("Watching '{}' for changes", input.display()));
+	let mut config = config.clone();
+	let mut prev = None;
+
+	loop {
+		let mut current = snapshot(input, Some(out), &config);
+
+		if prev.as_ref() != Some(&current) {
+			if prev.is_some() {
+				reporter.status("Change found, rebuilding");
+				match reload() {
+					Ok(reloaded) => config = reloaded,
+					Err(err) => reporter.error(&err),
+				}
+				// The reloaded config may depend on other files
+				current = snapshot(input, Some(out), &config);
+			}
+			if let Err(err) = rebuild(input, out, &config, reporter) {
+				reporter.error(&err);
+			}
+			prev = Some(current);
+		}
+
+		thread::sleep(INTERVAL);
+	}
+}
+
+fn rebuild(input: &Path, out: &Path, config: &Config, reporter: &Reporter) -> CatResult<()> {
+	if input.is_dir() {
+		build::build(input, out, config, reporter)
+	} else {
+		let html = eval_html(input, config, reporter)?;
+		build::write(out, html)
+	}
+}
+
+/// Finds when every file within the input (except the output directory and anything ignored)
+/// was last modified, alongside the config file and any data files that may be loaded,
+/// skipping any that cannot be read.
+pub fn snapshot(input: &Path, out: Option<&Path>, config: &Config) -> Snapshot {
+	// `@data` loads files from beside the file being evaluated (which is already within the
+	// input if it is a directory), or from any included directory
+	let beside = input.is_file().then(|| match input.parent() {
+		Some(dir) if !dir.as_os_str().is_empty() => dir,
+		_ => Path::new("."),
+	});
+	let data = (beside.into_iter())
+		.chain(config.include.iter().map(PathBuf::as_path))
+		.flat_map(|dir| build::entries(dir, out, config))
+		.filter_map(Result::ok)
+		.map(DirEntry::into_path)
+		.filter(|path| DataFormat::from_path(path).is_some());
+
+	build::entries(input, out, config)
+		.filter_map(Result::ok)
+		.map(DirEntry::into_path)
+		.chain(data)
+		.chain(config.file.iter().cloned())
+		.chain(config.data_files.iter().cloned())
+		.filter_map(|path| {
+			let metadata = fs::metadata(&path).ok()?;
+			let modified = metadata.modified().ok()?;
+			metadata
+				.is_file()
+				.then_some((path, (modified, metadata.len())))
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use teacat_lib::testing::TempDir;
+
+	use super::*;
+	use crate::config::CONFIG_NAME;
+
+	#[test]
+	fn snapshots_dependencies() {
+		let temp = TempDir::new("watch_snapshot");
+		let dir = &temp.0;
+		for file in [
+			"index.tcat",
+			"team.json",
+			"notes.txt",
+			"include/site.toml",
+			"other.json",
+			CONFIG_NAME,
+		] {
+			let path = dir.join(file);
+			fs::create_dir_all(path.parent().unwrap()).unwrap();
+			fs::write(path, "").unwrap();
+		}
+
+		let mut config = Config::read(&dir.join(CONFIG_NAME)).unwrap();
+		config.include = vec![dir.join("include")];
+		config.data_files = vec![dir.join("other.json")];
+
+		// Data files beside the file and within included directories are also watched
+		let paths: Vec<PathBuf> = (snapshot(&dir.join("index.tcat"), None, &config).into_keys())
+			.map(|path| path.strip_prefix(dir).unwrap().into())
+			.collect();
+		assert_eq!(
+			paths,
+			[
+				"include/site.toml".into(),
+				"index.tcat".into(),
+				"other.json".into(),
+				PathBuf::from(CONFIG_NAME),
+				"team.json".into(),
+			]
+		);
+	}
+}
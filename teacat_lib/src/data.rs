@@ -0,0 +1,190 @@
+//! Loading data files (written in JSON or TOML) as variables.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde_json::{Map, Value};
+
+use crate::prelude::*;
+
+/// The formats that data files can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataFormat {
+	Json,
+	Toml,
+}
+
+impl DataFormat {
+	/// Finds the format of a data file from its extension.
+	pub fn from_path(path: &Path) -> Option<Self> {
+		match path.extension()?.to_str()? {
+			"json" => Some(Self::Json),
+			"toml" => Some(Self::Toml),
+			_ => None,
+		}
+	}
+}
+
+/// Parses a data string, with any errors being returned as a message.
+/// # Examples
+/// ```
+/// use teacat_lib::data::{DataFormat, parse_data};
+///
+/// let json = parse_data(r#"{"name": "TeaCat"}"#, DataFormat::Json).unwrap();
+/// let toml = parse_data("name = 'TeaCat'", DataFormat::Toml).unwrap();
+///
+/// assert_eq!(json, toml);
+/// ```
+pub fn parse_data(str: &str, format: DataFormat) -> Result<Value, String> {
+	match format {
+		DataFormat::Json => serde_json::from_str(str).map_err(|err| err.to_string()),
+		DataFormat::Toml => toml::from_str(str)
+			.map(from_toml)
+			.map_err(|err| err.message().to_string()),
+	}
+}
+
+/// Reads and parses a data file, using its extension to find its format.
+pub fn read_data(path: &Path) -> Result<Value, String> {
+	let Some(format) = DataFormat::from_path(path) else {
+		return Err(format!("'{}' is not a JSON or TOML file", path.display()));
+	};
+
+	let str = fs::read_to_string(path)
+		.map_err(|err| format!("could not read '{}': {err}", path.display()))?;
+	parse_data(&str, format).map_err(|err| format!("'{}' is invalid: {err}", path.display()))
+}
+
+/// Converts data into variables, named after the provided name.
+///
+/// Objects are split into a variable for each of their fields (so `person` becomes
+/// `&person.name`), and lists become arrays, with each of their items also being available
+/// through their index (such as `&team.0.name`). Within arrays, objects are left empty, and
+/// can be shown by applying a macro to each item with `@each`.
+/// # Examples
+/// ```
+/// use serde_json::json;
+/// use teacat_lib::{data::data_vars, prelude::*};
+///
+/// let vars = data_vars("person", &json!({ "name": "Juni", "pets": ["Cat"] }));
+///
+/// assert_eq!(vars["person.name"], ExpandedAst::text("Juni"));
+/// assert_eq!(
+/// 	vars["person.pets"],
+/// 	ExpandedAst(vec![ExpandedNode::Array(vec![ExpandedAst::text("Cat")])])
+/// );
+/// ```
+pub fn data_vars(name: &str, value: &Value) -> BTreeMap<String, ExpandedAst> {
+	let mut vars = BTreeMap::new();
+	define(&mut vars, name, value);
+	vars
+}
+
+fn define(vars: &mut BTreeMap<String, ExpandedAst>, name: &str, value: &Value) {
+	match value {
+		Value::Object(fields) => {
+			for (key, value) in fields {
+				define(vars, &format!("{name}.{key}"), value);
+			}
+		}
+		Value::Array(items) => {
+			for (i, item) in items.iter().enumerate() {
+				define(vars, &format!("{name}.{i}"), item);
+			}
+			vars.insert(name.into(), expanded(value));
+		}
+		_ => {
+			vars.insert(name.into(), expanded(value));
+		}
+	}
+}
+
+/// Converts data into the contents of a variable.
+fn expanded(value: &Value) -> ExpandedAst {
+	match value {
+		Value::String(str) => ExpandedAst::text(str),
+		Value::Bool(_) | Value::Number(_) => ExpandedAst::text(value.to_string()),
+		Value::Array(items) => ExpandedAst(vec![ExpandedNode::Array(
+			items.iter().map(expanded).collect(),
+		)]),
+		Value::Null | Value::Object(_) => ExpandedAst(vec![]),
+	}
+}
+
+/// Converts a TOML table into the same form as a JSON object.
+pub(crate) fn from_toml_table(table: toml::Table) -> Map<String, Value> {
+	table
+		.into_iter()
+		.map(|(key, value)| (key, from_toml(value)))
+		.collect()
+}
+
+/// Converts TOML into the same form as JSON, with dates being written as strings.
+fn from_toml(value: toml::Value) -> Value {
+	match value {
+		toml::Value::String(str) => Value::String(str),
+		toml::Value::Integer(int) => int.into(),
+		toml::Value::Float(float) => float.into(),
+		toml::Value::Boolean(bool) => bool.into(),
+		toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+		toml::Value::Array(items) => Value::Array(items.into_iter().map(from_toml).collect()),
+		toml::Value::Table(table) => Value::Object(from_toml_table(table)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+
+	#[test]
+	fn vars() {
+		let vars = data_vars(
+			"team",
+			&json!([
+				{ "name": "Juni", "admin": true },
+				{ "name": "Sam", "admin": null },
+			]),
+		);
+
+		assert_eq!(
+			vars.keys().collect::<Vec<_>>(),
+			[
+				"team",
+				"team.0.admin",
+				"team.0.name",
+				"team.1.admin",
+				"team.1.name"
+			]
+		);
+		assert_eq!(vars["team.0.admin"], ExpandedAst::text("true"));
+		assert_eq!(vars["team.1.admin"], ExpandedAst(vec![]));
+		assert_eq!(
+			vars["team"],
+			ExpandedAst(vec![ExpandedNode::Array(vec![
+				ExpandedAst(vec![]),
+				ExpandedAst(vec![])
+			])])
+		);
+	}
+
+	#[test]
+	fn toml() {
+		let value = parse_data(
+			"date = 2025-01-01\n[[releases]]\nversion = 1.5",
+			DataFormat::Toml,
+		)
+		.unwrap();
+
+		assert_eq!(
+			value,
+			json!({ "date": "2025-01-01", "releases": [{ "version": 1.5 }] })
+		);
+		assert!(parse_data("x =", DataFormat::Toml).is_err());
+		assert_eq!(
+			DataFormat::from_path(Path::new("data.json")),
+			Some(DataFormat::Json)
+		);
+		assert_eq!(DataFormat::from_path(Path::new("data.yaml")), None);
+	}
+}
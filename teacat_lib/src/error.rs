@@ -1,39 +1,104 @@
 use std::{
 	error::Error,
 	fmt::{Display, Formatter, Result},
+	io,
+	path::PathBuf,
+	sync::Arc,
 };
 
 use anstyle::{AnsiColor, Color, Style};
 
-use crate::lexer::Token;
-use lines::Lines;
+use crate::{
+	expanded::Defined,
+	lexer::{Pos, Token},
+};
+use diagnostics::Severity;
+use lines::{Label, Lines, Span, primary_span};
 
+pub mod codes;
+pub mod diagnostics;
 pub mod lines;
 
 const VERT: char = '│';
 const VERT_DASH: char = '┆';
 
+const ERR: Style = colorstyle(AnsiColor::Red);
+const WARN: Style = colorstyle(AnsiColor::Yellow);
+const NOTE: Style = colorstyle(AnsiColor::Cyan);
 const HELP: Style = colorstyle(AnsiColor::Magenta);
 const DARK: Style = colorstyle(AnsiColor::BrightBlack);
 const DEFAULT: Style = colorstyle(AnsiColor::White);
 const BOLD: Style = Style::new().bold();
 
+/// Options for how errors are displayed by [TeaCatError::err_fancy_with].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportOptions {
+	/// The number of lines shown before and after each line that is pointed at.
+	pub context: usize,
+	/// Whether or not ANSI escape codes are used to colour the output.
+	pub color: bool,
+	/// The name of the file being reported on, which is shown alongside the line and column.
+	pub name: Option<String>,
+}
+
+/// The result of any fallible operation on TeaCat files.
+pub type CatResult<T> = std::result::Result<T, TeaCatError>;
+
 #[derive(Debug, Clone)]
 pub enum TeaCatError {
-	UndefinedVar(usize, String),
-	UndefinedMacr(usize, String),
-	UnexpectedAttr(usize, Token),
-	UnexpectedToken(usize, Token),
-	ExpectedIdent(usize, Token),
-	ExpectedSemicolon(usize, Token),
-	EarlyEof(usize, Token),
+	/// A variable was used without being defined, alongside the closest defined name (and
+	/// where it was defined), if there is one.
+	UndefinedVar(Pos, String, Option<(Defined, String)>),
+	/// A macro was used without being defined, alongside the closest defined name (and where
+	/// it was defined), if there is one.
+	UndefinedMacr(Pos, String, Option<(Pos, String)>),
+	UnexpectedAttr(Pos, Token),
+	UnexpectedToken(Pos, Token),
+	ExpectedIdent(Pos, Token),
+	ExpectedSemicolon(Pos, Token),
+	/// The file ended while a [Token] was expected, with the position being that of the last
+	/// token.
+	EarlyEof(Pos, Token),
+	/// The first [Token] (opened at the position) was never closed by the second before the
+	/// file ended.
+	Unclosed(Pos, Token, Token),
+	UnterminatedStr(Pos, char),
+	UnterminatedComment(Pos),
+	UnmatchedCommentClose(Pos),
+	/// A macro expanded into itself, or macros were nested deeper than the limit (the second
+	/// value). Contains every macro being expanded, alongside where it was used, from the
+	/// outermost to the one that failed.
+	RecursiveMacr(Vec<(Pos, String)>, usize),
+	/// A data file could not be loaded with `@data`, alongside the reason why.
+	InvalidData(Pos, String),
+	/// A macro could not be applied to each item of a list with `@each`, alongside the reason
+	/// why.
+	InvalidEach(Pos, String),
+	/// The front matter at the start of a TeaCat string is not valid TOML, alongside the reason
+	/// why.
+	InvalidFrontMatter(Pos, String),
+	/// The front matter at the start of a TeaCat string is never closed by another `+++` line.
+	UnclosedFrontMatter,
+	/// Formatting would have changed what a TeaCat string means, alongside the line where it
+	/// first differs (if it is known). This is always a bug in the formatter.
+	InternalFormatter(Option<usize>),
+	/// A file could not be read or written.
+	Io(PathBuf, Arc<io::Error>),
+}
+
+/// A [TeaCatError] within a [Source](crate::Source), alongside the name of that source
+/// (usually the path of its file) if it has one.
+#[derive(Debug, Clone)]
+pub struct SourceError {
+	pub name: Option<String>,
+	pub error: TeaCatError,
 }
 
 impl Display for TeaCatError {
 	fn fmt(&self, f: &mut Formatter<'_>) -> Result {
 		f.write_str(&match self {
-			Self::UndefinedVar(_, var) => format!("variable '&{var}' undefined"),
-			Self::UndefinedMacr(_, macr) => format!("macro '&{macr}' undefined"),
+			Self::UndefinedVar(_, var, _) => format!("variable '&{var}' undefined"),
+			Self::UndefinedMacr(_, macr, _) => format!("macro '@{macr}' undefined"),
 			Self::UnexpectedAttr(_, token) => {
 				format!("unexpected input in attributes: '{token}'")
 			}
@@ -43,39 +108,132 @@ impl Display for TeaCatError {
 			Self::ExpectedIdent(_, token) => format!("expected identifier, found '{token}'"),
 			Self::ExpectedSemicolon(_, token) => format!("expected ';', found '{token}'"),
 			Self::EarlyEof(_, token) => format!("early end of file while seeking token '{token}'"),
+			Self::Unclosed(pos, Token::Walrus, close) => {
+				format!(
+					"variable defined on line {} never ended with '{close}'",
+					pos.line + 1
+				)
+			}
+			Self::Unclosed(pos, open, _) => {
+				format!("'{open}' opened on line {} never closed", pos.line + 1)
+			}
+			Self::UnterminatedStr(_, quotes) => {
+				format!("unterminated string literal, opened with '{quotes}'")
+			}
+			Self::UnterminatedComment(_) => "unterminated multi-line comment".into(),
+			Self::UnmatchedCommentClose(_) => "'#>' without a matching '<#'".into(),
+			Self::RecursiveMacr(chain, max_depth) => match cycle_start(chain) {
+				Some(start) => format!(
+					"macro '@{}' expands into itself: {}",
+					chain[start].1,
+					macr_chain(&chain[start..])
+				),
+				None => format!(
+					"macros are nested more than {max_depth} deep: {}",
+					macr_chain(chain)
+				),
+			},
+			Self::InvalidData(_, reason) => format!("could not load data: {reason}"),
+			Self::InvalidEach(_, reason) => format!("could not use '@each': {reason}"),
+			Self::InvalidFrontMatter(_, reason) => format!("invalid front matter: {reason}"),
+			Self::UnclosedFrontMatter => "the front matter is never closed".into(),
+			Self::InternalFormatter(_) => {
+				"formatting would have changed what the file means".into()
+			}
+			Self::Io(path, err) => format!("could not access '{}': {err}", path.display()),
 		})
 	}
 }
 
-impl Error for TeaCatError {}
+impl Error for TeaCatError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Io(_, err) => Some(err.as_ref()),
+			_ => None,
+		}
+	}
+}
+
+impl Display for SourceError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+		match &self.name {
+			Some(name) => write!(f, "{name}: {}", self.error),
+			None => write!(f, "{}", self.error),
+		}
+	}
+}
+
+impl Error for SourceError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		self.error.source()
+	}
+}
+
+impl From<SourceError> for TeaCatError {
+	fn from(err: SourceError) -> Self {
+		err.error
+	}
+}
 
 impl TeaCatError {
-	fn line_num(&self) -> usize {
+	/// Creates an [Io](TeaCatError::Io) error for the provided path.
+	pub fn io(path: impl Into<PathBuf>, err: io::Error) -> Self {
+		Self::Io(path.into(), Arc::new(err))
+	}
+
+	/// Gets the line that the error occured on, if it occured within a TeaCat string.
+	pub fn line_num(&self) -> Option<usize> {
 		macro_rules! get_line {
 			( $( $name:ident, )* ) => {
 				match self {
-					$( | TeaCatError::$name(line, ..) )* => *line,
+					$( | TeaCatError::$name(pos, ..) )* => Some(pos.line),
+					TeaCatError::RecursiveMacr(chain, _) => chain.last().map(|(pos, _)| pos.line),
+					TeaCatError::UnclosedFrontMatter => Some(0),
+					TeaCatError::InternalFormatter(line) => *line,
+					TeaCatError::Io(..) => None,
 				}
 			};
 		}
 
 		get_line!(
 			EarlyEof,
+			Unclosed,
 			UndefinedVar,
 			UndefinedMacr,
 			UnexpectedToken,
 			UnexpectedAttr,
 			ExpectedSemicolon,
 			ExpectedIdent,
+			UnterminatedStr,
+			UnterminatedComment,
+			UnmatchedCommentClose,
+			InvalidData,
+			InvalidEach,
+			InvalidFrontMatter,
 		)
 	}
 
 	pub fn help_msg(&self) -> String {
 		match self {
-			Self::UndefinedVar(_, var) => {
+			Self::UndefinedVar(_, _, Some((Defined::Predefined, similar))) => {
+				format!("a variable with a similar name was predefined: '&{similar}'")
+			}
+			Self::UndefinedVar(_, _, Some((Defined::At(pos), similar))) => {
+				format!(
+					"a variable with a similar name was defined on line {}: '&{similar}'",
+					pos.line + 1
+				)
+			}
+			Self::UndefinedVar(_, var, None) => {
 				format!("to insert an '&' directly, use a backslash: '\\&{var}'")
 			}
-			Self::UndefinedMacr(_, macr) => {
+			Self::UndefinedMacr(_, _, Some((pos, similar))) => {
+				format!(
+					"a macro with a similar name was defined on line {}: '@{similar}'",
+					pos.line + 1
+				)
+			}
+			Self::UndefinedMacr(_, macr, None) => {
 				format!("to insert an '@' directly, use a backslash: '\\@{macr}'")
 			}
 			Self::UnexpectedAttr(_, token) => {
@@ -91,20 +249,383 @@ impl TeaCatError {
 			Self::EarlyEof(_, token) => {
 				format!("add the expected token to the end of the file: '..{token}'")
 			}
+			Self::Unclosed(_, _, close) => format!("add a matching '{close}'"),
+			Self::UnterminatedStr(_, quotes) => {
+				format!(
+					"close the string literal with a matching '{quotes}', or escape it: '\\{quotes}'"
+				)
+			}
+			Self::UnterminatedComment(_) => "close the comment with '#>'".into(),
+			Self::UnmatchedCommentClose(_) => {
+				"remove the '#>', or escape it with a backslash: '\\#>'".into()
+			}
+			Self::RecursiveMacr(chain, _) if cycle_start(chain).is_some() => {
+				"macros cannot expand into themselves, even through other macros".into()
+			}
+			Self::RecursiveMacr(..) => {
+				"reduce how deeply macros are nested within each other, or raise the limit".into()
+			}
+			Self::InvalidData(..) => {
+				"data files are JSON or TOML, and are loaded with '@data[&file := data.json;]'"
+					.into()
+			}
+			Self::InvalidEach(..) => {
+				"lists are used with '@each[&list := team; &apply := member;]', which passes the fields of each item to the macro".into()
+			}
+			Self::UnclosedFrontMatter => {
+				"close the front matter with a '+++' line, or escape the first one: '\\+++'".into()
+			}
+			Self::InvalidFrontMatter(..) => {
+				"front matter is written in TOML, such as 'title = \"Home\"'".into()
+			}
+			Self::InternalFormatter(_) => {
+				"this is a bug in the formatter, so the file was left as it is; please report it"
+					.into()
+			}
+			Self::Io(path, _) => {
+				format!("check that '{}' exists and can be accessed", path.display())
+			}
+		}
+	}
+
+	/// Gets the stable code for the kind of error, which can be explained by [codes::explain].
+	pub fn code(&self) -> &'static str {
+		match self {
+			Self::UndefinedVar(..) => "TC0001",
+			Self::UndefinedMacr(..) => "TC0002",
+			Self::UnexpectedAttr(..) => "TC0003",
+			Self::UnexpectedToken(..) => "TC0004",
+			Self::ExpectedIdent(..) => "TC0005",
+			Self::ExpectedSemicolon(..) => "TC0006",
+			Self::EarlyEof(..) => "TC0007",
+			Self::UnterminatedStr(..) => "TC0008",
+			Self::UnterminatedComment(..) => "TC0009",
+			Self::UnmatchedCommentClose(..) => "TC0010",
+			Self::Io(..) => "TC0011",
+			Self::Unclosed(..) => "TC0017",
+			Self::RecursiveMacr(..) => "TC0018",
+			Self::InvalidData(..) => "TC0019",
+			Self::InvalidFrontMatter(..) => "TC0020",
+			Self::InvalidEach(..) => "TC0022",
+			Self::UnclosedFrontMatter => "TC0023",
+			Self::InternalFormatter(_) => "TC0024",
+		}
+	}
+
+	/// Finds the part of the TeaCat string that caused the error, if it occured within one.
+	/// # Examples
+	/// ```
+	/// use teacat_lib::prelude::*;
+	///
+	/// let str = ":head[]\n:body[&content]";
+	/// let span = eval_teacat_string::<HtmlRenderer, String>(str)
+	/// 	.unwrap_err()
+	/// 	.span(str)
+	/// 	.unwrap();
+	///
+	/// assert_eq!((span.line, span.start, span.end), (1, 6, 14));
+	/// ```
+	pub fn span(&self, teacat_str: impl AsRef<str>) -> Option<Span> {
+		primary_span(&self.labels(), teacat_str.as_ref())
+	}
+
+	/// Gets the parts of the TeaCat string that caused the error.
+	pub(crate) fn labels(&self) -> Vec<Label> {
+		// Text starts at its first visible char, so any whitespace around it is not pointed at
+		let text = |token: &Token| Some(token.to_string().trim().into());
+
+		match self {
+			Self::UndefinedVar(pos, var, similar) => {
+				let mut labels = vec![];
+				if let Some((Defined::At(def_pos), similar)) = similar {
+					labels.push(Label::secondary(
+						*def_pos,
+						Some(format!("&{similar}")),
+						"similar variable defined here",
+					));
+				}
+				labels.push(Label::primary(*pos, Some(format!("&{var}")), "not defined"));
+				labels
+			}
+			Self::UndefinedMacr(pos, macr, similar) => {
+				let mut labels = vec![];
+				if let Some((def_pos, similar)) = similar {
+					labels.push(Label::secondary(
+						*def_pos,
+						Some(format!("@{similar}")),
+						"similar macro declared here",
+					));
+				}
+				labels.push(Label::primary(
+					*pos,
+					Some(format!("@{macr}")),
+					"not defined",
+				));
+				labels
+			}
+			Self::UnexpectedAttr(pos, token) | Self::UnexpectedToken(pos, token) => {
+				vec![Label::primary(*pos, text(token), "unexpected")]
+			}
+			Self::ExpectedIdent(pos, token) => {
+				vec![Label::primary(*pos, text(token), "expected an identifier")]
+			}
+			Self::ExpectedSemicolon(pos, token) => {
+				vec![Label::primary(
+					*pos,
+					text(token),
+					"expected ';' before this",
+				)]
+			}
+			Self::EarlyEof(pos, token) => {
+				vec![Label::primary(*pos, None, format!("expected '{token}'"))]
+			}
+			Self::Unclosed(pos, open, close) => vec![
+				Label::primary(
+					*pos,
+					text(open),
+					match open {
+						Token::Walrus => "defined here",
+						_ => "opened here",
+					},
+				),
+				Label::end_of_file(format!("expected '{close}'")),
+			],
+			Self::UnterminatedStr(pos, quotes) => {
+				vec![Label::primary(
+					*pos,
+					Some(quotes.to_string()),
+					"string literal opened here",
+				)]
+			}
+			Self::UnterminatedComment(pos) => {
+				vec![Label::primary(
+					*pos,
+					Some("<#".into()),
+					"comment opened here",
+				)]
+			}
+			Self::UnmatchedCommentClose(pos) => {
+				vec![Label::primary(*pos, Some("#>".into()), "no matching '<#'")]
+			}
+			Self::RecursiveMacr(chain, _) => {
+				let call = |(pos, name): &(Pos, String), message| {
+					Label::secondary(*pos, Some(format!("@{name}[")), message)
+				};
+				let Some((last, rest)) = chain.split_last() else {
+					return vec![];
+				};
+
+				let (mut labels, message): (Vec<Label>, _) = match cycle_start(chain) {
+					Some(start) => (
+						rest[start..]
+							.iter()
+							.map(|macr| call(macr, "expanded here"))
+							.collect(),
+						"expanded again here",
+					),
+					None => (
+						rest.first()
+							.map(|macr| call(macr, "outermost macro"))
+							.into_iter()
+							.collect(),
+						"nested too deeply",
+					),
+				};
+				labels.push(Label {
+					primary: true,
+					..call(last, message)
+				});
+				labels
+			}
+			Self::InvalidData(pos, _) => {
+				vec![Label::primary(*pos, Some("@data[".into()), "loaded here")]
+			}
+			Self::InvalidEach(pos, _) => {
+				vec![Label::primary(*pos, Some("@each[".into()), "used here")]
+			}
+			Self::UnclosedFrontMatter => {
+				vec![Label::primary(
+					Pos::new(0, 0),
+					Some("+++".into()),
+					"opened here",
+				)]
+			}
+			Self::InvalidFrontMatter(pos, _) => {
+				vec![Label::primary(*pos, None, "invalid here")]
+			}
+			Self::InternalFormatter(line) => (line.iter())
+				.map(|line| Label::primary(Pos::new(*line, 0), None, "first changed here"))
+				.collect(),
+			Self::Io(..) => vec![],
 		}
 	}
 
 	pub fn err_fancy(&self, teacat_str: impl Into<String>) -> String {
-		let help = format!(
-			"{DARK}    ╰─▶ {HELP}{BOLD}help: {}{BOLD:#}{DEFAULT}",
-			self.help_msg()
-		);
-		let lines = Lines::new(self.line_num(), teacat_str);
+		self.err_fancy_with(teacat_str, &ReportOptions::default())
+	}
+
+	/// Displays the error in the same way as [TeaCatError::err_fancy], but without any colours.
+	/// # Examples
+	/// ```
+	/// use teacat_lib::prelude::*;
+	///
+	/// let str = ":p[&name]";
+	/// let err = eval_teacat_string::<HtmlRenderer, String>(str).unwrap_err();
+	///
+	/// assert!(!err.err_plain(str).contains('\x1b'));
+	/// ```
+	pub fn err_plain(&self, teacat_str: impl Into<String>) -> String {
+		self.err_fancy_with(teacat_str, &ReportOptions::plain())
+	}
+
+	/// Displays the error alongside the lines that caused it, using the provided [ReportOptions].
+	pub fn err_fancy_with(&self, teacat_str: impl Into<String>, options: &ReportOptions) -> String {
+		fancy(
+			self,
+			self.code(),
+			&self.help_msg(),
+			&self.labels(),
+			Severity::Error,
+			&teacat_str.into(),
+			options,
+		)
+	}
+}
+
+impl SourceError {
+	/// Names an error, if there is a name.
+	pub fn new(name: Option<&str>, error: TeaCatError) -> Self {
+		Self {
+			name: name.map(String::from),
+			error,
+		}
+	}
+
+	pub fn err_fancy(&self, teacat_str: impl Into<String>) -> String {
+		self.err_fancy_with(teacat_str, &ReportOptions::default())
+	}
+
+	/// Displays the error in the same way as [SourceError::err_fancy], but without any colours.
+	pub fn err_plain(&self, teacat_str: impl Into<String>) -> String {
+		self.err_fancy_with(teacat_str, &ReportOptions::plain())
+	}
+
+	/// Displays the error in the same way as [TeaCatError::err_fancy_with], with its name
+	/// shown alongside the line and column (instead of [ReportOptions::name]).
+	pub fn err_fancy_with(&self, teacat_str: impl Into<String>, options: &ReportOptions) -> String {
+		let options = ReportOptions {
+			name: self.name.clone().or(options.name.clone()),
+			..options.clone()
+		};
+		self.error.err_fancy_with(teacat_str, &options)
+	}
+}
+
+impl ReportOptions {
+	/// The default options, without any colours.
+	pub fn plain() -> Self {
+		Self {
+			color: false,
+			..Self::default()
+		}
+	}
+
+	/// Gets the provided [Style], or an empty one if colours are disabled.
+	fn style(&self, style: Style) -> Style {
+		if self.color { style } else { Style::new() }
+	}
+}
+
+impl Default for ReportOptions {
+	fn default() -> Self {
+		Self {
+			context: 1,
+			color: true,
+			name: None,
+		}
+	}
+}
+
+/// Finds where the last macro in a [RecursiveMacr](TeaCatError::RecursiveMacr) chain was
+/// first expanded, if it was already being expanded.
+fn cycle_start(chain: &[(Pos, String)]) -> Option<usize> {
+	let (_, last) = chain.last()?;
+	chain
+		.iter()
+		.position(|(_, name)| name == last)
+		.filter(|start| start + 1 < chain.len())
+}
+
+/// Formats a chain of macros, such as `@a → @b → @a`, leaving out the middle of long chains.
+fn macr_chain(chain: &[(Pos, String)]) -> String {
+	let names: Vec<String> = chain.iter().map(|(_, name)| format!("@{name}")).collect();
 
-		format!("{BOLD}{self}{BOLD:#}\n\n{lines}{DARK}    {VERT_DASH}\n{help}\n")
+	if names.len() > 6 {
+		format!(
+			"{} → … → {}",
+			names[..3].join(" → "),
+			names[names.len() - 2..].join(" → ")
+		)
+	} else {
+		names.join(" → ")
 	}
 }
 
+/// Formats a message alongside the lines around where it occured, and a help message.
+fn fancy(
+	message: impl Display,
+	code: &str,
+	help_msg: &str,
+	labels: &[Label],
+	severity: Severity,
+	teacat_str: &str,
+	options: &ReportOptions,
+) -> String {
+	let [dark, help_style, default, bold] = [DARK, HELP, DEFAULT, BOLD].map(|s| options.style(s));
+
+	let help = format!("{dark}    ╰─▶ {help_style}{bold}help: {help_msg}{bold:#}{default}");
+	let message = format!("{bold}{message}{bold:#} {dark}[{code}]{default}");
+	if labels.is_empty() {
+		return format!("{message}\n{help}\n");
+	}
+
+	let style = options.style(match severity {
+		Severity::Error => ERR,
+		Severity::Warning => WARN,
+		Severity::Note => NOTE,
+	});
+	let lines = Lines::new(labels, teacat_str, options, style);
+
+	let location = primary_span(labels, teacat_str)
+		.map(|span| format!("{}:{}", span.line + 1, span.start + 1))
+		.unwrap_or_default();
+	let location = match &options.name {
+		Some(name) => format!("{name}:{location}"),
+		None => location,
+	};
+
+	format!("{message}\n{dark}    ╭─[{location}]\n{lines}{dark}    {VERT_DASH}\n{help}\n")
+}
+
 const fn colorstyle(color: AnsiColor) -> Style {
 	Style::new().fg_color(Some(Color::Ansi(color)))
 }
+
+#[cfg(test)]
+mod tests {
+	use crate::prelude::*;
+
+	#[test]
+	fn token_spans() {
+		let span = |str: &str| {
+			let err = Ast::parse(TokenStream::lex(str).unwrap()).unwrap_err();
+			let span = err.span(str).unwrap();
+			(span.line, span.start, span.end)
+		};
+
+		// Whitespace before text is not pointed at
+		assert_eq!(span(": p[x]"), (0, 2, 3));
+		assert_eq!(span("& x := 1;"), (0, 2, 3));
+		assert_eq!(span(":p[a]\n:br;\n:p[\n\t  b c ]:  d[]"), (3, 11, 12));
+	}
+}
@@ -0,0 +1,82 @@
+//! Long-form explanations for each error and warning code.
+
+macro_rules! codes {
+	( $( $code:ident, )* ) => {
+		/// Every code, alongside its explanation (written in Markdown).
+		pub const CODES: &[(&str, &str)] = &[
+			$( (stringify!($code), include_str!(concat!("codes/", stringify!($code), ".md"))), )*
+		];
+	};
+}
+
+codes!(
+	TC0001, TC0002, TC0003, TC0004, TC0005, TC0006, TC0007, TC0008, TC0009, TC0010, TC0011, TC0012,
+	TC0013, TC0014, TC0015, TC0016, TC0017, TC0018, TC0019, TC0020, TC0021, TC0022, TC0023, TC0024,
+);
+
+/// Gets the explanation for an error or warning code, such as `TC0001`.
+/// # Examples
+/// ```
+/// use teacat_lib::error::codes::explain;
+///
+/// assert!(explain("TC0001").unwrap().starts_with("A variable was used without being defined."));
+/// assert!(explain("tc0001").is_some());
+/// assert!(explain("TC9999").is_none());
+/// ```
+pub fn explain(code: &str) -> Option<&'static str> {
+	CODES
+		.iter()
+		.find(|(other, _)| other.eq_ignore_ascii_case(code.trim()))
+		.map(|(_, explanation)| *explanation)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::ErrorKind;
+
+	use super::*;
+	use crate::{lexer::Token, prelude::*};
+
+	#[test]
+	fn all_explained() {
+		let errors = [
+			TeaCatError::UndefinedVar(Pos::default(), "x".into(), None),
+			TeaCatError::UndefinedMacr(Pos::default(), "x".into(), None),
+			TeaCatError::UnexpectedAttr(Pos::default(), Token::Colon),
+			TeaCatError::UnexpectedToken(Pos::default(), Token::Colon),
+			TeaCatError::ExpectedIdent(Pos::default(), Token::Colon),
+			TeaCatError::ExpectedSemicolon(Pos::default(), Token::Colon),
+			TeaCatError::EarlyEof(Pos::default(), Token::Colon),
+			TeaCatError::Unclosed(Pos::default(), Token::OpenBracket, Token::CloseBracket),
+			TeaCatError::UnterminatedStr(Pos::default(), '"'),
+			TeaCatError::UnterminatedComment(Pos::default()),
+			TeaCatError::UnmatchedCommentClose(Pos::default()),
+			TeaCatError::RecursiveMacr(vec![(Pos::default(), "x".into())], 64),
+			TeaCatError::InvalidData(Pos::default(), "x".into()),
+			TeaCatError::InvalidEach(Pos::default(), "x".into()),
+			TeaCatError::InvalidFrontMatter(Pos::new(0, 0), "x".into()),
+			TeaCatError::UnclosedFrontMatter,
+			TeaCatError::InternalFormatter(None),
+			TeaCatError::io("x", ErrorKind::NotFound.into()),
+		];
+		let warnings = [
+			TeaCatWarning::UnusedVar(Pos::default(), "x".into()),
+			TeaCatWarning::UnusedMacr(Pos::default(), "x".into()),
+			TeaCatWarning::ShadowedVar(Pos::default(), "x".into(), Pos::default()),
+			TeaCatWarning::DuplicateAttr(Pos::default(), "x".into(), Pos::default()),
+			TeaCatWarning::EmptyVoidTag(Pos::default(), "x".into()),
+			TeaCatWarning::ShadowedMacr(Pos::default(), "x".into(), Pos::default()),
+		];
+
+		let mut codes: Vec<&str> = errors
+			.iter()
+			.map(TeaCatError::code)
+			.chain(warnings.iter().map(TeaCatWarning::code))
+			.collect();
+
+		assert!(codes.iter().all(|code| explain(code).is_some()));
+		codes.sort_unstable();
+		codes.dedup();
+		assert_eq!(codes.len(), CODES.len());
+	}
+}
@@ -0,0 +1,306 @@
+use std::fmt::{Display, Formatter, Result};
+
+use super::{
+	ReportOptions, TeaCatError, fancy,
+	lines::{Label, Span, primary_span},
+};
+use crate::lexer::Pos;
+
+/// How serious a [Diagnostic] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	Note,
+	Warning,
+	Error,
+}
+
+/// A problem within a TeaCat string that does not stop it from being evaluated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TeaCatWarning {
+	UnusedVar(Pos, String),
+	UnusedMacr(Pos, String),
+	/// A variable was defined while another with the same name was in scope, defined at the
+	/// second position.
+	ShadowedVar(Pos, String, Pos),
+	/// An attribute was set more than once on the same tag, first being set at the second
+	/// position.
+	DuplicateAttr(Pos, String, Pos),
+	/// A void element (such as `br`) was given empty contents.
+	EmptyVoidTag(Pos, String),
+	/// A macro was defined while another with the same name was in scope, defined at the
+	/// second position.
+	ShadowedMacr(Pos, String, Pos),
+}
+
+/// Either a [TeaCatError] or a [TeaCatWarning].
+#[derive(Debug, Clone)]
+pub enum Diagnostic {
+	Error(TeaCatError),
+	Warning(TeaCatWarning),
+}
+
+/// Every [Diagnostic] found while evaluating a TeaCat string, in the order they were found.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Display for Severity {
+	fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+		f.write_str(match self {
+			Self::Note => "note",
+			Self::Warning => "warning",
+			Self::Error => "error",
+		})
+	}
+}
+
+impl Display for TeaCatWarning {
+	fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+		f.write_str(&match self {
+			Self::UnusedVar(_, var) => format!("variable '&{var}' is never used"),
+			Self::UnusedMacr(_, macr) => format!("macro '@{macr}' is never used"),
+			Self::ShadowedVar(_, var, _) => {
+				format!("variable '&{var}' shadows a previous definition")
+			}
+			Self::DuplicateAttr(_, key, _) => format!("attribute '{key}' is set more than once"),
+			Self::EmptyVoidTag(_, name) => format!("'{name}' is a void element"),
+			Self::ShadowedMacr(_, macr, _) => {
+				format!("macro '@{macr}' shadows a previous definition")
+			}
+		})
+	}
+}
+
+impl TeaCatWarning {
+	pub fn severity(&self) -> Severity {
+		match self {
+			Self::EmptyVoidTag(..) => Severity::Note,
+			_ => Severity::Warning,
+		}
+	}
+
+	/// Gets where the warning occured.
+	pub fn pos(&self) -> Pos {
+		match self {
+			Self::UnusedVar(pos, ..)
+			| Self::UnusedMacr(pos, ..)
+			| Self::ShadowedVar(pos, ..)
+			| Self::DuplicateAttr(pos, ..)
+			| Self::EmptyVoidTag(pos, ..)
+			| Self::ShadowedMacr(pos, ..) => *pos,
+		}
+	}
+
+	/// Gets the line that the warning occured on.
+	pub fn line_num(&self) -> usize {
+		self.pos().line
+	}
+
+	pub fn help_msg(&self) -> String {
+		match self {
+			Self::UnusedVar(_, var) => format!("remove the variable, or use it: '&{var}'"),
+			Self::UnusedMacr(_, macr) => format!("remove the macro, or use it: '@{macr}[]'"),
+			Self::ShadowedVar(_, var, prev) => {
+				format!(
+					"'&{var}' was previously defined on line {}, consider renaming one of them",
+					prev.line + 1
+				)
+			}
+			Self::DuplicateAttr(_, key, _) => {
+				format!("only the last value of '{key}' is used, remove the others")
+			}
+			Self::EmptyVoidTag(_, name) => format!("void elements have no contents: ':{name};'"),
+			Self::ShadowedMacr(_, macr, prev) => {
+				format!(
+					"'@{macr}' was previously defined on line {}, consider renaming one of them",
+					prev.line + 1
+				)
+			}
+		}
+	}
+
+	/// Gets the stable code for the kind of warning, which can be explained by
+	/// [codes::explain](super::codes::explain).
+	pub fn code(&self) -> &'static str {
+		match self {
+			Self::UnusedVar(..) => "TC0012",
+			Self::UnusedMacr(..) => "TC0013",
+			Self::ShadowedVar(..) => "TC0014",
+			Self::DuplicateAttr(..) => "TC0015",
+			Self::EmptyVoidTag(..) => "TC0016",
+			Self::ShadowedMacr(..) => "TC0021",
+		}
+	}
+
+	/// Finds the part of the TeaCat string that caused the warning.
+	pub fn span(&self, teacat_str: impl AsRef<str>) -> Span {
+		primary_span(&self.labels(), teacat_str.as_ref()).expect("Warnings always have a label")
+	}
+
+	/// Gets the parts of the TeaCat string that caused the warning.
+	pub(crate) fn labels(&self) -> Vec<Label> {
+		match self {
+			Self::UnusedVar(pos, var) => {
+				vec![Label::primary(
+					*pos,
+					Some(format!("&{var}")),
+					"defined here",
+				)]
+			}
+			Self::UnusedMacr(pos, macr) => {
+				vec![Label::primary(
+					*pos,
+					Some(format!("@{macr}")),
+					"declared here",
+				)]
+			}
+			Self::ShadowedVar(pos, var, prev) => vec![
+				Label::secondary(*prev, Some(format!("&{var}")), "previously defined here"),
+				Label::primary(*pos, Some(format!("&{var}")), "defined again here"),
+			],
+			Self::DuplicateAttr(pos, key, first) => vec![
+				Label::secondary(*first, Some(format!("{key}:")), "first set here"),
+				Label::primary(*pos, Some(format!("{key}:")), "set again here"),
+			],
+			Self::EmptyVoidTag(pos, name) => {
+				vec![Label::primary(
+					*pos,
+					Some(format!(":{name}")),
+					"has no contents",
+				)]
+			}
+			Self::ShadowedMacr(pos, macr, prev) => vec![
+				Label::secondary(*prev, Some(format!("@{macr}")), "previously defined here"),
+				Label::primary(*pos, Some(format!("@{macr}")), "defined again here"),
+			],
+		}
+	}
+
+	pub fn err_fancy(&self, teacat_str: impl Into<String>) -> String {
+		self.err_fancy_with(teacat_str, &ReportOptions::default())
+	}
+
+	/// Displays the warning without any colours.
+	pub fn err_plain(&self, teacat_str: impl Into<String>) -> String {
+		self.err_fancy_with(teacat_str, &ReportOptions::plain())
+	}
+
+	/// Displays the warning alongside the lines that caused it, using the provided
+	/// [ReportOptions].
+	pub fn err_fancy_with(&self, teacat_str: impl Into<String>, options: &ReportOptions) -> String {
+		fancy(
+			self,
+			self.code(),
+			&self.help_msg(),
+			&self.labels(),
+			self.severity(),
+			&teacat_str.into(),
+			options,
+		)
+	}
+}
+
+impl Display for Diagnostic {
+	fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+		match self {
+			Self::Error(err) => err.fmt(f),
+			Self::Warning(warning) => warning.fmt(f),
+		}
+	}
+}
+
+impl Diagnostic {
+	pub fn severity(&self) -> Severity {
+		match self {
+			Self::Error(_) => Severity::Error,
+			Self::Warning(warning) => warning.severity(),
+		}
+	}
+
+	pub fn code(&self) -> &'static str {
+		match self {
+			Self::Error(err) => err.code(),
+			Self::Warning(warning) => warning.code(),
+		}
+	}
+
+	pub fn span(&self, teacat_str: impl AsRef<str>) -> Option<Span> {
+		match self {
+			Self::Error(err) => err.span(teacat_str),
+			Self::Warning(warning) => Some(warning.span(teacat_str)),
+		}
+	}
+
+	pub fn help_msg(&self) -> String {
+		match self {
+			Self::Error(err) => err.help_msg(),
+			Self::Warning(warning) => warning.help_msg(),
+		}
+	}
+
+	pub fn err_fancy(&self, teacat_str: impl Into<String>) -> String {
+		self.err_fancy_with(teacat_str, &ReportOptions::default())
+	}
+
+	/// Displays the diagnostic without any colours.
+	pub fn err_plain(&self, teacat_str: impl Into<String>) -> String {
+		self.err_fancy_with(teacat_str, &ReportOptions::plain())
+	}
+
+	pub fn err_fancy_with(&self, teacat_str: impl Into<String>, options: &ReportOptions) -> String {
+		match self {
+			Self::Error(err) => err.err_fancy_with(teacat_str, options),
+			Self::Warning(warning) => warning.err_fancy_with(teacat_str, options),
+		}
+	}
+}
+
+impl From<TeaCatError> for Diagnostic {
+	fn from(err: TeaCatError) -> Self {
+		Self::Error(err)
+	}
+}
+
+impl From<TeaCatWarning> for Diagnostic {
+	fn from(warning: TeaCatWarning) -> Self {
+		Self::Warning(warning)
+	}
+}
+
+impl Diagnostics {
+	pub fn new() -> Self {
+		Self(vec![])
+	}
+
+	/// Adds a [Diagnostic], unless the same warning has already been reported (such as when
+	/// a macro is used several times).
+	pub fn push(&mut self, diagnostic: impl Into<Diagnostic>) {
+		let diagnostic = diagnostic.into();
+
+		if let Diagnostic::Warning(ref warning) = diagnostic
+			&& self
+				.0
+				.iter()
+				.any(|other| matches!(other, Diagnostic::Warning(other) if other == warning))
+		{
+			return;
+		}
+
+		self.0.push(diagnostic);
+	}
+
+	/// Counts the [Diagnostics](Diagnostic) with the provided [Severity].
+	pub fn count(&self, severity: Severity) -> usize {
+		self.0
+			.iter()
+			.filter(|diagnostic| diagnostic.severity() == severity)
+			.count()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+		self.0.iter()
+	}
+}
@@ -1,26 +1,126 @@
 use std::fmt::{Display, Formatter, Result};
 
-use super::{DARK, DEFAULT, VERT};
+use anstyle::Style;
 
+use super::{DARK, DEFAULT, ReportOptions, VERT, VERT_DASH};
+use crate::lexer::Pos;
+
+/// Points at part of a line within a fancy error, alongside a message explaining it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Label {
+	pub pos: Pos,
+	/// The text being pointed at (which starts at the position), or [None] to point at the end
+	/// of the line.
+	pub text: Option<String>,
+	pub message: String,
+	/// Whether the label is the cause of the error, rather than extra context.
+	pub primary: bool,
+}
+
+/// The location of part of a line within a TeaCat string. Lines and columns start at zero,
+/// and columns are counted in [chars](char).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+	pub line: usize,
+	pub start: usize,
+	/// The column after the end of the span.
+	pub end: usize,
+}
+
+/// The lines of a TeaCat string around a set of [Labels](Label), with each label underlined.
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
-pub(super) struct Lines([Option<(usize, String)>; 3]);
+pub(super) struct Lines {
+	rows: Vec<Row>,
+	dark: Style,
+	default: Style,
+}
 
-impl Lines {
-	pub fn new(num: usize, text: impl Into<String>) -> Self {
-		let text = "\n".to_string() + &text.into();
-		let lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
-		let mut out = Self::default();
-
-		if let Some(i) = num.checked_sub(1) {
-			if let Some(line) = lines.get(i) {
-				out.0[0] = Some((i, line.into()));
-			}
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Row {
+	Source(usize, String),
+	Underline {
+		start: usize,
+		len: usize,
+		message: String,
+		style: Style,
+		primary: bool,
+	},
+	/// Lines that were skipped between two labels.
+	Gap,
+}
+
+impl Label {
+	pub fn primary(pos: Pos, text: Option<String>, message: impl Into<String>) -> Self {
+		Self {
+			pos,
+			text,
+			message: message.into(),
+			primary: true,
 		}
-		if let Some(line) = lines.get(num) {
-			out.0[1] = Some((num, line.into()));
+	}
+
+	pub fn secondary(pos: Pos, text: Option<String>, message: impl Into<String>) -> Self {
+		Self {
+			primary: false,
+			..Self::primary(pos, text, message)
 		}
-		if let Some(line) = lines.get(num + 1) {
-			out.0[2] = Some((num + 1, line.into()));
+	}
+
+	/// Creates a secondary label pointing at the end of the last line.
+	pub fn end_of_file(message: impl Into<String>) -> Self {
+		Self::secondary(Pos::new(usize::MAX, 0), None, message)
+	}
+}
+
+impl Lines {
+	/// Finds the lines around each [Label] (with the context lines from the [ReportOptions]
+	/// before and after them). The primary labels are underlined with the provided [Style].
+	pub fn new(labels: &[Label], text: &str, options: &ReportOptions, style: Style) -> Self {
+		let context = options.context;
+		let lines = split_lines(text);
+		let spans = spans(labels, text);
+
+		let mut shown: Vec<usize> = spans
+			.iter()
+			.flat_map(|span| span.line.saturating_sub(context)..=span.line + context)
+			.filter(|num| *num < lines.len())
+			.collect();
+		shown.sort_unstable();
+		shown.dedup();
+
+		let mut out = Self {
+			rows: vec![],
+			dark: options.style(DARK),
+			default: options.style(DEFAULT),
+		};
+		let mut prev = None;
+
+		for num in shown {
+			if prev.is_some_and(|prev| prev + 1 < num) {
+				out.rows.push(Row::Gap);
+			}
+			prev = Some(num);
+
+			let line = lines[num];
+			out.rows.push(Row::Source(num, expand_tabs(line)));
+
+			let mut underlines: Vec<(&Label, &Span)> = labels
+				.iter()
+				.zip(&spans)
+				.filter(|(_, span)| span.line == num)
+				.collect();
+			underlines.sort_by_key(|(_, span)| span.start);
+
+			for (label, span) in underlines {
+				let start = display_width(line, span.start);
+				out.rows.push(Row::Underline {
+					start,
+					len: (display_width(line, span.end) - start).max(1),
+					message: label.message.clone(),
+					style: if label.primary { style } else { out.dark },
+					primary: label.primary,
+				});
+			}
 		}
 
 		out
@@ -29,16 +129,204 @@ impl Lines {
 
 impl Display for Lines {
 	fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-		let mut out = String::new();
+		let Self { dark, default, .. } = self;
 
-		for line in &self.0 {
-			out.push_str(&if let Some((num, str)) = line {
-				format!("{DARK}{num:3} {VERT} {DEFAULT}{str}\n")
-			} else {
-				format!("{DARK}    {VERT} {DEFAULT}\n")
+		for row in &self.rows {
+			match row {
+				Row::Source(num, str) => {
+					writeln!(f, "{dark}{:3} {VERT} {default}{str}", num + 1)?;
+				}
+				Row::Underline {
+					start,
+					len,
+					message,
+					style,
+					primary,
+				} => {
+					let underline = if *primary { "^" } else { "-" }.repeat(*len);
+					writeln!(
+						f,
+						"{dark}    {VERT} {}{style}{underline} {message}{style:#}",
+						" ".repeat(*start)
+					)?;
+				}
+				Row::Gap => writeln!(f, "{dark}    {VERT_DASH}")?,
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// Finds the [Span] of each [Label]. Spans never go past the end of their line, so text that
+/// continues onto the next line is only pointed at until the end of the first.
+pub(crate) fn spans(labels: &[Label], text: &str) -> Vec<Span> {
+	let lines = split_lines(text);
+
+	// Errors at the end of the file may be reported on a line that does not exist, so are
+	// moved to the last line with any text on it
+	let last = lines
+		.iter()
+		.rposition(|line| !line.trim().is_empty())
+		.unwrap_or(lines.len() - 1);
+
+	labels
+		.iter()
+		.map(|label| {
+			let (line, text) = match lines.get(label.pos.line) {
+				Some(_) => (label.pos.line, label.text.as_deref()),
+				None => (last, None),
+			};
+			let len = lines[line].chars().count();
+
+			let (start, end) = match text {
+				Some(text) => {
+					let start = label.pos.col.min(len);
+					(
+						start,
+						(start + text.chars().count()).min(len).max(start + 1),
+					)
+				}
+				None => (len, len + 1),
+			};
+			Span { line, start, end }
+		})
+		.collect()
+}
+
+/// Finds the [Span] of the first primary [Label].
+pub(crate) fn primary_span(labels: &[Label], text: &str) -> Option<Span> {
+	labels
+		.iter()
+		.zip(spans(labels, text))
+		.find(|(label, _)| label.primary)
+		.map(|(_, span)| span)
+}
+
+/// Splits a string into lines, keeping the empty line after a trailing newline.
+fn split_lines(text: &str) -> Vec<&str> {
+	text.split('\n')
+		.map(|line| line.strip_suffix('\r').unwrap_or(line))
+		.collect()
+}
+
+fn expand_tabs(line: &str) -> String {
+	line.replace('\t', "    ")
+}
+
+/// Gets the width of the first `chars` characters of a line, once tabs have been expanded.
+fn display_width(line: &str, chars: usize) -> usize {
+	let width: usize = line
+		.chars()
+		.take(chars)
+		.map(|ch| if ch == '\t' { 4 } else { 1 })
+		.sum();
+
+	width + chars.saturating_sub(line.chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn options(context: usize) -> ReportOptions {
+		ReportOptions {
+			context,
+			..ReportOptions::plain()
+		}
+	}
+
+	fn plain(lines: Lines) -> String {
+		let mut out = String::new();
+		for row in lines.rows {
+			out.push_str(&match row {
+				Row::Source(num, str) => format!("{}|{str}\n", num + 1),
+				Row::Underline {
+					start,
+					len,
+					message,
+					primary,
+					..
+				} => format!(
+					"|{}{} {message}\n",
+					" ".repeat(start),
+					if primary { "^" } else { "-" }.repeat(len)
+				),
+				Row::Gap => "...\n".into(),
 			});
 		}
+		out
+	}
+
+	#[test]
+	fn underlines() {
+		let text = "&x := X;\n:p[\n\t&y &x\n]";
+		let labels = [
+			Label::secondary(Pos::new(0, 0), Some("&x".into()), "defined here"),
+			Label::primary(Pos::new(2, 4), Some("&x".into()), "used here"),
+		];
+
+		assert_eq!(
+			plain(Lines::new(&labels, text, &options(0), DARK)),
+			"1|&x := X;\n|-- defined here\n...\n3|    &y &x\n|       ^^ used here\n"
+		);
+	}
+
+	#[test]
+	fn edges() {
+		let text = "first\nsecond\n";
+
+		// The first line has no line before it
+		assert_eq!(
+			plain(Lines::new(
+				&[Label::primary(Pos::new(0, 0), None, "here")],
+				text,
+				&options(1),
+				DARK
+			)),
+			"1|first\n|     ^ here\n2|second\n"
+		);
+		// Lines past the end of the file are moved to the last line that is not blank
+		assert_eq!(
+			plain(Lines::new(
+				&[Label::end_of_file("here")],
+				text,
+				&options(1),
+				DARK
+			)),
+			"1|first\n2|second\n|      - here\n3|\n"
+		);
+	}
+
+	#[test]
+	fn exact_columns() {
+		// The same text on a line is pointed at where the label is, rather than where it is
+		// first found
+		let labels = [
+			Label::secondary(Pos::new(0, 19), Some("&x".into()), "second"),
+			Label::primary(Pos::new(0, 9), Some("&x".into()), "first"),
+			Label::primary(Pos::new(1, 1), Some("'a\nb'".into()), "multi-line"),
+		];
 
-		f.write_str(&out)
+		assert_eq!(
+			spans(&labels, "&x := 1; &x := 2; &x := 3;\n:'a\nb'"),
+			vec![
+				Span {
+					line: 0,
+					start: 19,
+					end: 21
+				},
+				Span {
+					line: 0,
+					start: 9,
+					end: 11
+				},
+				Span {
+					line: 1,
+					start: 1,
+					end: 3
+				},
+			]
+		);
 	}
 }
@@ -1,113 +1,970 @@
-use std::collections::HashMap;
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	env,
+	path::{Component, Path, PathBuf},
+	rc::Rc,
+};
+
+use strsim::damerau_levenshtein;
 
 use crate::{
-	parser::{AstNode, Tag},
+	data::{data_vars, read_data},
+	error::diagnostics::{Diagnostics, TeaCatWarning},
+	parser::{AstNode, Tag, Var},
 	prelude::*,
 };
 
+mod html_parser;
 pub mod renderer;
 
 /// An [Ast] that has had all variables/macros expanded out and removed, and is ready for rendering.
-#[derive(Debug, Clone)]
-pub struct ExpandedAst(Vec<ExpandedNode>);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpandedAst(pub Vec<ExpandedNode>);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExpandedNode {
 	Text(String),
 	Tag(ExpandedTag),
 	Array(Vec<ExpandedAst>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ExpandedTag {
 	pub name: String,
 	pub attributes: Attributes,
 	pub contents: ExpandedAst,
 }
 
+/// Options for how an [Ast] is expanded by [ExpandedAst::expand_with].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpandOptions {
+	/// The most macros that can be expanded within each other before a
+	/// [RecursiveMacr](TeaCatError::RecursiveMacr) error is returned.
+	pub max_depth: usize,
+	/// Variables that are defined before expanding, such as those set by a project. These are
+	/// also available within macros, and can be replaced without a warning.
+	pub vars: BTreeMap<String, ExpandedAst>,
+	/// The directories that data files loaded with `@data` are found within, checked in order.
+	/// If there are none, data files cannot be loaded.
+	pub data_dirs: Vec<PathBuf>,
+	/// Which environment variables can be used, as `&env.` followed by their name (such as
+	/// `&env.HOME`). These are read when they are used, and only if no variable with the same
+	/// name is defined.
+	pub env: EnvAccess,
+}
+
+/// Which environment variables can be used within a TeaCat string.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum EnvAccess {
+	/// None of them, as they may contain secrets.
+	#[default]
+	None,
+	/// Only those with the provided names.
+	Only(BTreeSet<String>),
+	/// All of them.
+	All,
+}
+
+/// What the names of environment variables start with when used as variables.
+const ENV_PREFIX: &str = "env.";
+
+/// The name of the macro that loads data files, unless a macro with the same name is defined.
+const DATA_MACR: &str = "data";
+
+/// The name of the macro that applies another macro to each item of a list, unless a macro with
+/// the same name is defined.
+const EACH_MACR: &str = "each";
+
+/// Where a variable or macro was defined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Defined {
+	/// At the provided position within the TeaCat string.
+	At(Pos),
+	/// Outside of the TeaCat string's body, through [ExpandOptions::vars] or front matter.
+	Predefined,
+}
+
 impl ExpandedAst {
+	/// Creates an [ExpandedAst] containing only text.
+	pub fn text(text: impl Into<String>) -> Self {
+		Self(vec![ExpandedNode::Text(text.into())])
+	}
+
 	/// Expands an [Ast], removing all variables/macros.
 	pub fn expand(ast: Ast) -> CatResult<Self> {
-		Self::expand_inner(ast, &HashMap::new(), &HashMap::new())
+		Self::expand_with_diagnostics(ast, &mut Diagnostics::new())
 	}
 
-	fn expand_inner(
+	/// Expands an [Ast], adding any warnings (such as unused variables) to the provided
+	/// [Diagnostics].
+	pub fn expand_with_diagnostics(ast: Ast, diagnostics: &mut Diagnostics) -> CatResult<Self> {
+		Self::expand_with(ast, &ExpandOptions::default(), diagnostics)
+	}
+
+	/// Expands an [Ast] using the provided [ExpandOptions], adding any warnings to the provided
+	/// [Diagnostics].
+	/// # Errors
+	/// Alongside undefined variables and macros, returns a
+	/// [RecursiveMacr](TeaCatError::RecursiveMacr) error if a macro expands into itself, or if
+	/// macros are nested deeper than [ExpandOptions::max_depth].
+	/// # Examples
+	/// ```
+	/// use teacat_lib::prelude::*;
+	///
+	/// let tokenstream = TokenStream::lex("macr @a{}[@b[]]\nmacr @b{}[@a[]]\n@a[]").unwrap();
+	/// let ast = Ast::parse(tokenstream).unwrap();
+	/// let err = ExpandedAst::expand_with(ast, &ExpandOptions::default(), &mut Diagnostics::new());
+	///
+	/// assert_eq!(
+	/// 	err.unwrap_err().to_string(),
+	/// 	"macro '@a' expands into itself: @a → @b → @a"
+	/// );
+	/// ```
+	pub fn expand_with(
 		ast: Ast,
-		vars: &HashMap<String, ExpandedAst>,
-		macrs: &HashMap<String, Ast>,
+		options: &ExpandOptions,
+		diagnostics: &mut Diagnostics,
 	) -> CatResult<Self> {
-		let mut expanded = Self(vec![]);
+		Self::expand_borrowed(&ast, options, &BTreeMap::new(), diagnostics)
+	}
+
+	/// Expands an [Ast] without taking ownership of it, in the same way as
+	/// [ExpandedAst::expand_with]. The `metadata` variables are defined alongside (and replace)
+	/// [ExpandOptions::vars].
+	pub(crate) fn expand_borrowed(
+		ast: &Ast,
+		options: &ExpandOptions,
+		metadata: &BTreeMap<String, ExpandedAst>,
+		diagnostics: &mut Diagnostics,
+	) -> CatResult<Self> {
+		let mut expander = Expander {
+			vars: vec![],
+			macrs: vec![],
+			warnings: vec![],
+			calls: vec![],
+			options,
+			metadata,
+		};
+		let expanded = expander.expand_inner(ast, &HashMap::new(), &HashMap::new())?;
+
+		let mut warnings = expander.warnings;
+		for var in expander.vars.into_iter().filter(|var| !var.used) {
+			warnings.push(TeaCatWarning::UnusedVar(var.pos, var.name));
+		}
+		for macr in expander.macrs.into_iter().filter(|macr| !macr.used) {
+			warnings.push(TeaCatWarning::UnusedMacr(macr.pos, macr.name));
+		}
+
+		warnings.sort_by_key(TeaCatWarning::pos);
+		for warning in warnings {
+			diagnostics.push(warning);
+		}
+
+		Ok(expanded)
+	}
+}
+
+/// The variables or macros defined within the TeaCat string that are in scope, alongside the
+/// ids of their definitions. Variables from [ExpandOptions::vars] are looked up separately, so
+/// that they are never copied.
+type Scope<T> = HashMap<String, (usize, T)>;
+
+/// A variable or macro definition within the TeaCat string.
+struct Definition {
+	pos: Pos,
+	name: String,
+	used: bool,
+}
+
+/// Keeps track of which variables and macros are used while expanding an [Ast].
+struct Expander<'a> {
+	/// Every variable definition, with its index as its id. Each definition gets a new id
+	/// when it is expanded, so definitions with the same name at the same position are kept
+	/// apart.
+	vars: Vec<Definition>,
+	/// Every macro definition, with its index as its id.
+	macrs: Vec<Definition>,
+	warnings: Vec<TeaCatWarning>,
+	/// The macros currently being expanded, as where each was used alongside the id of its
+	/// definition.
+	calls: Vec<(Pos, usize)>,
+	options: &'a ExpandOptions,
+	/// Variables from front matter, which replace those within [ExpandOptions::vars].
+	metadata: &'a BTreeMap<String, ExpandedAst>,
+}
+
+impl EnvAccess {
+	/// Whether the environment variable with the provided name can be used.
+	pub fn allows(&self, name: &str) -> bool {
+		match self {
+			Self::None => false,
+			Self::Only(names) => names.contains(name),
+			Self::All => true,
+		}
+	}
+}
+
+impl Defined {
+	/// The position of the definition, if it is within the TeaCat string.
+	pub fn pos(self) -> Option<Pos> {
+		match self {
+			Self::At(pos) => Some(pos),
+			Self::Predefined => None,
+		}
+	}
+}
+
+impl Default for ExpandOptions {
+	fn default() -> Self {
+		Self {
+			max_depth: 64,
+			vars: BTreeMap::new(),
+			data_dirs: vec![],
+			env: EnvAccess::None,
+		}
+	}
+}
+
+impl<'a> Expander<'a> {
+	fn expand_inner(
+		&mut self,
+		ast: &'a Ast,
+		vars: &Scope<Rc<ExpandedAst>>,
+		macrs: &Scope<&'a Ast>,
+	) -> CatResult<ExpandedAst> {
+		let mut expanded = ExpandedAst(vec![]);
 		let mut vars = vars.clone();
 		let mut macrs = macrs.clone();
 
-		for node in ast.0 {
+		for node in &ast.0 {
 			match node {
 				AstNode::Var(var) => {
-					vars.insert(
-						var.name,
-						ExpandedAst::expand_inner(var.contents, &vars, &macrs)?,
-					);
+					let contents = self.expand_inner(&var.contents, &vars, &macrs)?;
+					self.define_var(&mut vars, var.pos, var.name.clone(), contents);
 				}
-				AstNode::AccessVar(line, var) => {
-					let Some(contents) = vars.get(&var) else {
-						return Err(TeaCatError::UndefinedVar(line, var).into());
+				AstNode::AccessVar(pos, var) => {
+					let env;
+					let contents = if let Some((id, contents)) = vars.get(var) {
+						self.vars[*id].used = true;
+						&**contents
+					} else if let Some(contents) = self.predefined(var) {
+						contents
+					} else if let Some(value) = self.env_var(var) {
+						env = ExpandedAst::text(value);
+						&env
+					} else {
+						return Err(self.undefined_var(*pos, var, &vars));
 					};
-					expanded.0.append(&mut contents.0.clone());
+					expanded.0.extend_from_slice(&contents.0);
 				}
 
-				AstNode::AccessMacr(line, args, name) => {
+				AstNode::AccessMacr(pos, args, name)
+					if name == DATA_MACR && !macrs.contains_key(name) =>
+				{
+					self.load_data(*pos, args, &mut vars, &macrs)?;
+				}
+				AstNode::AccessMacr(pos, args, name)
+					if name == EACH_MACR && !macrs.contains_key(name) =>
+				{
+					let mut expanded_each = self.each(*pos, args, &vars, &macrs)?;
+					expanded.0.append(&mut expanded_each.0);
+				}
+				AstNode::AccessMacr(pos, args, name) => {
 					let mut macr_vars = HashMap::new();
 
 					for arg in args {
-						macr_vars.insert(
-							arg.name,
-							ExpandedAst::expand_inner(arg.contents, &vars, &macrs)?,
-						);
+						let contents = self.expand_inner(&arg.contents, &vars, &macrs)?;
+						self.define_var(&mut macr_vars, arg.pos, arg.name.clone(), contents);
 					}
 
-					let Some(macr) = macrs.get(&name) else {
-						return Err(TeaCatError::UndefinedMacr(line, name).into());
-					};
-
-					let mut expanded_macr =
-						ExpandedAst::expand_inner(macr.clone(), &macr_vars, &macrs)?;
+					let (id, macr) = self.find_macr(*pos, name, &macrs)?;
+					let mut expanded_macr = self.call_macr(*pos, id, macr, &macr_vars, &macrs)?;
 					expanded.0.append(&mut expanded_macr.0);
 				}
 				AstNode::Macr(macr) => {
-					macrs.insert(macr.name, macr.contents);
+					let id = define(&mut self.macrs, macr.pos, macr.name.clone());
+
+					if let Some((prev, _)) = macrs.insert(macr.name.clone(), (id, &macr.contents)) {
+						let prev_pos = self.macrs[prev].pos;
+						self.warnings.push(TeaCatWarning::ShadowedMacr(
+							macr.pos,
+							macr.name.clone(),
+							prev_pos,
+						));
+					}
 				}
 
 				AstNode::Array(array) => {
 					let mut new = vec![];
 
 					for ast in array {
-						new.push(ExpandedAst::expand_inner(ast, &vars, &macrs)?);
+						new.push(self.expand_inner(ast, &vars, &macrs)?);
 					}
 
 					expanded.0.push(ExpandedNode::Array(new));
 				}
 
-				AstNode::Tag(tag) => expanded.0.push(ExpandedNode::Tag(ExpandedTag::from_tag(
-					tag, &vars, &macrs,
-				)?)),
-				AstNode::Text(text) => expanded.0.push(ExpandedNode::Text(text)),
+				AstNode::Tag(tag) => expanded
+					.0
+					.push(ExpandedNode::Tag(self.expand_tag(tag, &vars, &macrs)?)),
+				AstNode::Text(text) => expanded.0.push(ExpandedNode::Text(text.clone())),
 			}
 		}
 
 		Ok(expanded)
 	}
+
+	fn expand_tag(
+		&mut self,
+		tag: &'a Tag,
+		vars: &Scope<Rc<ExpandedAst>>,
+		macrs: &Scope<&'a Ast>,
+	) -> CatResult<ExpandedTag> {
+		Ok(ExpandedTag {
+			name: tag.name.clone(),
+			attributes: tag.attributes.clone(),
+			contents: self.expand_inner(&tag.contents, vars, macrs)?,
+		})
+	}
+
+	/// Creates an [UndefinedVar](TeaCatError::UndefinedVar) error, suggesting a variable with a
+	/// similar name if there is one.
+	fn undefined_var(&self, pos: Pos, var: &str, vars: &Scope<Rc<ExpandedAst>>) -> TeaCatError {
+		let defined = (vars.iter())
+			.map(|(name, (id, _))| (name, Defined::At(self.vars[*id].pos)))
+			.chain(
+				(self.metadata.keys())
+					.chain(self.options.vars.keys())
+					.map(|name| (name, Defined::Predefined)),
+			);
+		TeaCatError::UndefinedVar(pos, var.into(), similar(var, defined))
+	}
+
+	/// Finds the macro with the provided name, marking it as used.
+	fn find_macr(
+		&mut self,
+		pos: Pos,
+		name: &str,
+		macrs: &Scope<&'a Ast>,
+	) -> CatResult<(usize, &'a Ast)> {
+		let Some(&(id, macr)) = macrs.get(name) else {
+			let defined =
+				(macrs.iter()).map(|(name, (id, _))| (name, Defined::At(self.macrs[*id].pos)));
+			let similar = similar(name, defined)
+				.and_then(|(defined, similar)| Some((defined.pos()?, similar)));
+			return Err(TeaCatError::UndefinedMacr(pos, name.into(), similar));
+		};
+		self.macrs[id].used = true;
+		Ok((id, macr))
+	}
+
+	/// Expands a macro used at the provided position, with `vars` being its arguments.
+	fn call_macr(
+		&mut self,
+		pos: Pos,
+		id: usize,
+		macr: &'a Ast,
+		vars: &Scope<Rc<ExpandedAst>>,
+		macrs: &Scope<&'a Ast>,
+	) -> CatResult<ExpandedAst> {
+		self.enter_macr(pos, id)?;
+		let expanded = self.expand_inner(macr, vars, macrs)?;
+		self.calls.pop();
+		Ok(expanded)
+	}
+
+	/// Gets a variable defined outside of the TeaCat string's body.
+	fn predefined(&self, name: &str) -> Option<&'a ExpandedAst> {
+		(self.metadata.get(name)).or_else(|| self.options.vars.get(name))
+	}
+
+	/// Reads the environment variable used as the provided variable (such as `env.HOME`), if it
+	/// is set (as valid unicode) and allowed by [ExpandOptions::env].
+	fn env_var(&self, var: &str) -> Option<String> {
+		let name = var.strip_prefix(ENV_PREFIX)?;
+		self.options
+			.env
+			.allows(name)
+			.then(|| env::var(name).ok())
+			.flatten()
+	}
+
+	/// Loads a data file with `@data[&file := data.json;]`, defining its variables (named after
+	/// the file, or `&name` if it is given) within the current scope.
+	fn load_data(
+		&mut self,
+		pos: Pos,
+		args: &'a [Var],
+		vars: &mut Scope<Rc<ExpandedAst>>,
+		macrs: &Scope<&'a Ast>,
+	) -> CatResult<()> {
+		let invalid = |reason| TeaCatError::InvalidData(pos, reason);
+		let (mut file, mut name) = (None, None);
+
+		for arg in args {
+			let text = text(&self.expand_inner(&arg.contents, vars, macrs)?);
+			match arg.name.as_str() {
+				"file" => file = Some(text),
+				"name" => name = Some(text),
+				other => return Err(invalid(format!("unknown argument '&{other}'"))),
+			}
+		}
+
+		let Some(file) = file else {
+			return Err(invalid("no '&file' was given".into()));
+		};
+		if self.options.data_dirs.is_empty() {
+			return Err(invalid("data files cannot be loaded here".into()));
+		}
+		// Files are only found within the data directories, so they cannot be absolute or go up
+		let confined = Path::new(&file)
+			.components()
+			.all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+		if !confined {
+			return Err(invalid(format!(
+				"'{file}' must be relative, without any '..'"
+			)));
+		}
+		let Some(path) = (self.options.data_dirs)
+			.iter()
+			.map(|dir| dir.join(&file))
+			.find(|path| path.is_file())
+		else {
+			return Err(invalid(format!("'{file}' could not be found")));
+		};
+
+		let value = read_data(&path).map_err(invalid)?;
+		let name = name.unwrap_or_else(|| {
+			let stem = Path::new(&file).file_stem().unwrap_or_default();
+			stem.to_string_lossy().into_owned()
+		});
+
+		for (name, contents) in data_vars(&name, &value) {
+			// Data files often contain more than is needed, so unused entries are not warned about
+			let id = define(&mut self.vars, pos, name.clone());
+			self.vars[id].used = true;
+			vars.insert(name, (id, Rc::new(contents)));
+		}
+		Ok(())
+	}
+
+	/// Applies a macro to each item of a list with `@each[&list := team; &apply := member;]`.
+	/// The fields of each item are passed to the macro as arguments (so `&team.0.name` becomes
+	/// `&name`), with items that are not objects being passed as `&item`.
+	fn each(
+		&mut self,
+		pos: Pos,
+		args: &'a [Var],
+		vars: &Scope<Rc<ExpandedAst>>,
+		macrs: &Scope<&'a Ast>,
+	) -> CatResult<ExpandedAst> {
+		let invalid = |reason| TeaCatError::InvalidEach(pos, reason);
+		let (mut list, mut macr) = (None, None);
+
+		for arg in args {
+			let text = text(&self.expand_inner(&arg.contents, vars, macrs)?);
+			match arg.name.as_str() {
+				"list" => list = Some(text),
+				"apply" => macr = Some(text),
+				other => return Err(invalid(format!("unknown argument '&{other}'"))),
+			}
+		}
+
+		let Some(list) = list else {
+			return Err(invalid("no '&list' was given".into()));
+		};
+		let Some(macr) = macr else {
+			return Err(invalid("no '&apply' was given".into()));
+		};
+
+		let contents = if let Some((id, contents)) = vars.get(&list) {
+			self.vars[*id].used = true;
+			Rc::clone(contents)
+		} else if let Some(contents) = self.predefined(&list) {
+			Rc::new(contents.clone())
+		} else {
+			return Err(self.undefined_var(pos, &list, vars));
+		};
+		let [ExpandedNode::Array(list_items)] = &contents.0[..] else {
+			return Err(invalid(format!("'&{list}' is not a list")));
+		};
+		let (id, macr) = self.find_macr(pos, &macr, macrs)?;
+
+		// The fields of objects are found through the variables for each index (such as
+		// `&team.0.name`), with those defined within the TeaCat string replacing predefined ones
+		let mut fields: BTreeMap<usize, BTreeMap<String, Rc<ExpandedAst>>> = BTreeMap::new();
+		for (name, contents) in self.options.vars.iter().chain(self.metadata) {
+			if let Some((index, field)) = item_field(&list, name) {
+				let item = fields.entry(index).or_default();
+				item.insert(field.into(), Rc::new(contents.clone()));
+			}
+		}
+		for (name, (_, contents)) in vars {
+			if let Some((index, field)) = item_field(&list, name) {
+				let item = fields.entry(index).or_default();
+				item.insert(field.into(), Rc::clone(contents));
+			}
+		}
+		// Other items (such as those of lists within the TeaCat string) are passed as '&item'
+		let items: Vec<_> = (list_items.iter().enumerate())
+			.map(|(index, item)| {
+				let mut item_fields = fields.remove(&index).unwrap_or_default();
+				if item_fields.is_empty() {
+					item_fields.insert("item".into(), Rc::new(item.clone()));
+				}
+				item_fields
+			})
+			.collect();
+
+		let mut expanded = ExpandedAst(vec![]);
+		for fields in items {
+			let mut macr_vars = HashMap::new();
+			for (name, contents) in fields {
+				// Items often contain more than is needed, so unused fields are not warned about
+				let id = define(&mut self.vars, pos, name.clone());
+				self.vars[id].used = true;
+				macr_vars.insert(name, (id, contents));
+			}
+			expanded
+				.0
+				.append(&mut self.call_macr(pos, id, macr, &macr_vars, macrs)?.0);
+		}
+		Ok(expanded)
+	}
+
+	/// Records that a macro is being expanded, failing if it is already being expanded (as it
+	/// would never finish) or if too many macros are being expanded within each other.
+	fn enter_macr(&mut self, pos: Pos, id: usize) -> CatResult<()> {
+		let recursive = self.calls.iter().any(|(_, other)| *other == id);
+		self.calls.push((pos, id));
+
+		if recursive || self.calls.len() > self.options.max_depth {
+			let chain = self
+				.calls
+				.iter()
+				.map(|(pos, id)| (*pos, self.macrs[*id].name.clone()))
+				.collect();
+			return Err(TeaCatError::RecursiveMacr(chain, self.options.max_depth));
+		}
+
+		Ok(())
+	}
+
+	/// Adds a variable to the scope, warning if it replaces another (unless that one was
+	/// predefined).
+	fn define_var(
+		&mut self,
+		vars: &mut Scope<Rc<ExpandedAst>>,
+		pos: Pos,
+		name: String,
+		contents: ExpandedAst,
+	) {
+		let id = define(&mut self.vars, pos, name.clone());
+
+		if let Some((prev, _)) = vars.insert(name.clone(), (id, Rc::new(contents))) {
+			let prev_pos = self.vars[prev].pos;
+			self.warnings
+				.push(TeaCatWarning::ShadowedVar(pos, name, prev_pos));
+		}
+	}
 }
 
-impl ExpandedTag {
-	fn from_tag(
-		tag: Tag,
-		vars: &HashMap<String, ExpandedAst>,
-		macrs: &HashMap<String, Ast>,
-	) -> CatResult<Self> {
-		Ok(Self {
-			name: tag.name,
-			attributes: tag.attributes,
-			contents: ExpandedAst::expand_inner(tag.contents, vars, macrs)?,
+/// Adds a definition, returning its id.
+fn define(definitions: &mut Vec<Definition>, pos: Pos, name: String) -> usize {
+	definitions.push(Definition {
+		pos,
+		name,
+		used: false,
+	});
+	definitions.len() - 1
+}
+
+/// Splits the name of a variable within an item of a list (such as `team.0.name`) into the
+/// index of the item and the name of the field, which is `item` if the item is not an object.
+fn item_field<'n>(list: &str, name: &'n str) -> Option<(usize, &'n str)> {
+	let rest = name.strip_prefix(list)?.strip_prefix('.')?;
+	let (index, field) = rest.split_once('.').unwrap_or((rest, "item"));
+	Some((index.parse().ok()?, field))
+}
+
+/// Gets the text within an [ExpandedAst], ignoring any tags or arrays.
+fn text(ast: &ExpandedAst) -> String {
+	let text: String = ast
+		.0
+		.iter()
+		.filter_map(|node| match node {
+			ExpandedNode::Text(text) => Some(text.as_str()),
+			_ => None,
 		})
+		.collect();
+	text.trim().into()
+}
+
+/// Finds the defined name that is closest to the provided one, alongside where it was
+/// defined, as long as it is similar enough to likely be a typo.
+fn similar<'a>(
+	name: &str,
+	defined: impl Iterator<Item = (&'a String, Defined)>,
+) -> Option<(Defined, String)> {
+	let len = name.chars().count();
+	// Like rustc, never suggesting a name that shares nothing with this one, such as any
+	// other single character name
+	let max_distance = (len.max(3) / 3).min(len.saturating_sub(1));
+
+	defined
+		.map(|(other, defined)| (damerau_levenshtein(name, other), other, defined))
+		.filter(|(distance, ..)| *distance <= max_distance)
+		// Sorting by name as well, as the order of a HashMap is random
+		.min_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)))
+		.map(|(_, other, pos)| (pos, other.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::TempDir;
+
+	fn warnings(str: &str) -> Vec<Diagnostic> {
+		let mut diagnostics = Diagnostics::new();
+		let ast = Ast::parse(TokenStream::lex(str).unwrap()).unwrap();
+		ExpandedAst::expand_with_diagnostics(ast, &mut diagnostics).unwrap();
+		diagnostics.0
+	}
+
+	#[test]
+	fn unused() {
+		let diagnostics =
+			warnings("&x := X;\n&y := Y;\nmacr @m{&a}[&a]\nmacr @n{}[]\n&y @m[&a := A;]");
+
+		assert!(matches!(
+			diagnostics.as_slice(),
+			[
+				Diagnostic::Warning(TeaCatWarning::UnusedVar(Pos { line: 0, .. }, x)),
+				Diagnostic::Warning(TeaCatWarning::UnusedMacr(Pos { line: 3, .. }, n)),
+			] if x == "x" && n == "n"
+		));
+	}
+
+	#[test]
+	fn shadowed() {
+		// Each use of the macro should only warn once
+		let diagnostics =
+			warnings("&x := X;\n:p[&x := Y; &x]\nmacr @m{}[&y := 1; &y := 2; &y]\n@m[]@m[]");
+
+		assert!(matches!(
+			diagnostics.as_slice(),
+			[
+				Diagnostic::Warning(TeaCatWarning::UnusedVar(Pos { line: 0, .. }, x)),
+				Diagnostic::Warning(TeaCatWarning::ShadowedVar(Pos { line: 1, .. }, _, Pos { line: 0, .. })),
+				Diagnostic::Warning(TeaCatWarning::UnusedVar(Pos { line: 2, col: 10 }, _)),
+				Diagnostic::Warning(TeaCatWarning::ShadowedVar(Pos { line: 2, col: 19 }, y, Pos { line: 2, col: 10 })),
+			] if x == "x" && y == "y"
+		));
+
+		// Definitions on the same line are still tracked separately
+		assert!(matches!(
+			warnings("macr @a{}[x] macr @a{}[y] @a[]").as_slice(),
+			[
+				Diagnostic::Warning(TeaCatWarning::UnusedMacr(Pos { line: 0, col: 5 }, a)),
+				Diagnostic::Warning(TeaCatWarning::ShadowedMacr(Pos { line: 0, col: 18 }, _, Pos { line: 0, col: 5 })),
+			] if a == "a"
+		));
+	}
+
+	#[test]
+	fn suggestions() {
+		let err = |str| {
+			ExpandedAst::expand(Ast::parse(TokenStream::lex(str).unwrap()).unwrap()).unwrap_err()
+		};
+
+		assert!(matches!(
+			err("&title := T;\n&name := N;\n&titel"),
+			TeaCatError::UndefinedVar(Pos { line: 2, .. }, _, Some((Defined::At(Pos { line: 0, .. }), similar))) if similar == "title"
+		));
+		assert!(matches!(
+			err("&x := X;\n&completely_different"),
+			TeaCatError::UndefinedVar(Pos { line: 1, .. }, _, None)
+		));
+		assert!(matches!(
+			err("&x := X;\n&y"),
+			TeaCatError::UndefinedVar(Pos { line: 1, .. }, _, None)
+		));
+		assert!(matches!(
+			err("&ab := X;\n&ac"),
+			TeaCatError::UndefinedVar(Pos { line: 1, .. }, _, Some((_, similar))) if similar == "ab"
+		));
+		assert!(matches!(
+			err("macr @person{}[]\n@persn[]"),
+			TeaCatError::UndefinedMacr(Pos { line: 1, .. }, _, Some((Pos { line: 0, .. }, similar))) if similar == "person"
+		));
+	}
+
+	#[test]
+	fn recursion() {
+		let err = |str, max_depth| {
+			let ast = Ast::parse(TokenStream::lex(str).unwrap()).unwrap();
+			ExpandedAst::expand_with(
+				ast,
+				&ExpandOptions {
+					max_depth,
+					..ExpandOptions::default()
+				},
+				&mut Diagnostics::new(),
+			)
+			.unwrap_err()
+		};
+
+		assert!(matches!(
+			err("macr @a{}[@a[]]\n@a[]", 64),
+			TeaCatError::RecursiveMacr(chain, 64) if chain == [(Pos::new(1, 0), "a".into()), (Pos::new(0, 10), "a".into())]
+		));
+		assert_eq!(
+			err(
+				"macr @a{}[\n\t@b[]\n]\nmacr @b{}[@c[]]\nmacr @c{}[@b[]]\n:p[@a[]]",
+				64
+			)
+			.to_string(),
+			"macro '@b' expands into itself: @b → @c → @b"
+		);
+		assert_eq!(
+			err("macr @a{}[x]\nmacr @b{}[@a[]]\nmacr @c{}[@b[]]\n@c[]", 2).to_string(),
+			"macros are nested more than 2 deep: @c → @b → @a"
+		);
+
+		// A macro redefined within itself is a different macro, even on the same line
+		let ast = Ast::parse(TokenStream::lex("macr @a{}[macr @a{}[x] @a[]] @a[]").unwrap());
+		let expanded = ExpandedAst::expand(ast.unwrap()).unwrap();
+		assert_eq!(expanded, ExpandedAst::text("x"));
+	}
+
+	#[test]
+	fn predefined() {
+		let options = ExpandOptions {
+			vars: BTreeMap::from([
+				("site".into(), ExpandedAst::text("TeaCat")),
+				("title".into(), ExpandedAst::text("Home")),
+			]),
+			..ExpandOptions::default()
+		};
+		let expand = |str| {
+			let ast = Ast::parse(TokenStream::lex(str).unwrap()).unwrap();
+			let mut diagnostics = Diagnostics::new();
+			let expanded = ExpandedAst::expand_with(ast, &options, &mut diagnostics);
+			(expanded, diagnostics.0)
+		};
+
+		// Predefined variables can be used within macros, and replaced without a warning
+		let (expanded, diagnostics) = expand("&title := About;\nmacr @h{}[&site]\n@h[] &title");
+		assert_eq!(
+			expanded.unwrap(),
+			ExpandedAst(vec![
+				ExpandedNode::Text("TeaCat".into()),
+				ExpandedNode::Text("About".into()),
+			])
+		);
+		assert!(diagnostics.is_empty());
+
+		let (expanded, _) = expand("&titel");
+		let err = expanded.unwrap_err();
+		assert!(matches!(
+			&err,
+			TeaCatError::UndefinedVar(Pos { line: 0, .. }, _, Some((Defined::Predefined, similar))) if similar == "title"
+		));
+		assert_eq!(err.labels().len(), 1);
+	}
+
+	#[test]
+	fn env() {
+		// Cargo sets this while running tests
+		let name = env!("CARGO_PKG_NAME");
+		let expand = |str: &str, env, vars| {
+			let ast = Ast::parse(TokenStream::lex(str).unwrap()).unwrap();
+			let options = ExpandOptions {
+				vars,
+				env,
+				..ExpandOptions::default()
+			};
+			ExpandedAst::expand_with(ast, &options, &mut Diagnostics::new())
+		};
+		let only = |name: &str| EnvAccess::Only(BTreeSet::from([name.into()]));
+
+		assert_eq!(
+			expand("&env.CARGO_PKG_NAME", EnvAccess::All, BTreeMap::new()).unwrap(),
+			ExpandedAst::text(name)
+		);
+		assert_eq!(
+			expand(
+				"&env.CARGO_PKG_NAME",
+				only("CARGO_PKG_NAME"),
+				BTreeMap::new()
+			)
+			.unwrap(),
+			ExpandedAst::text(name)
+		);
+		assert!(matches!(
+			expand("&env.CARGO_PKG_NAME", only("HOME"), BTreeMap::new()),
+			Err(TeaCatError::UndefinedVar(Pos { line: 0, .. }, ..))
+		));
+		assert!(matches!(
+			expand("&env.CARGO_PKG_NAME", EnvAccess::None, BTreeMap::new()),
+			Err(TeaCatError::UndefinedVar(Pos { line: 0, .. }, ..))
+		));
+
+		// Variables with the same name are used instead of the environment
+		let vars = BTreeMap::from([("env.CARGO_PKG_NAME".into(), ExpandedAst::text("X"))]);
+		assert_eq!(
+			expand("&env.CARGO_PKG_NAME", EnvAccess::All, vars).unwrap(),
+			ExpandedAst::text("X")
+		);
+		assert_eq!(
+			expand(
+				"&env.CARGO_PKG_NAME := Y; &env.CARGO_PKG_NAME",
+				EnvAccess::All,
+				BTreeMap::new()
+			)
+			.unwrap(),
+			ExpandedAst::text("Y")
+		);
+	}
+
+	#[test]
+	fn data() {
+		let temp = TempDir::new("expanded_data");
+		let dir = &temp.0;
+		std::fs::write(
+			dir.join("team.json"),
+			r#"[{"name": "Juni"}, {"name": "Sam"}]"#,
+		)
+		.unwrap();
+
+		let options = ExpandOptions {
+			data_dirs: vec![dir.clone()],
+			..ExpandOptions::default()
+		};
+		let eval = |str: &str, options: &ExpandOptions| {
+			eval_teacat_string_with::<HtmlRenderer, String>(str, options, &mut Diagnostics::new())
+		};
+
+		assert_eq!(
+			eval(
+				"@data[&file := team.json;]\nmacr @m{&name}[&name]\n:p[&team.1.name]\n:ul[@each[&list := team; &apply := m;]]",
+				&options
+			)
+			.unwrap(),
+			"<!DOCTYPE html><html><p>Sam</p><ul>JuniSam</ul></html>"
+		);
+		assert_eq!(
+			eval(
+				"@data[&file := team.json; &name := people;]&people.0.name",
+				&options
+			)
+			.unwrap(),
+			"<!DOCTYPE html><html>Juni</html>"
+		);
+
+		// Macros named 'data' are used instead
+		assert_eq!(
+			eval("macr @data{&file}[&file]\n@data[&file := x;]", &options).unwrap(),
+			"<!DOCTYPE html><html>x</html>"
+		);
+
+		for str in [
+			"\n@data[&file := missing.json;]",
+			"\n@data[&file := team.json; &other := x;]",
+			"\n@data[&file := ../data/team.json;]",
+			"\n@data[&file := ./../team.json;]",
+		] {
+			assert!(matches!(
+				eval(str, &options),
+				Err(TeaCatError::InvalidData(Pos { line: 1, .. }, _))
+			));
+		}
+		assert!(matches!(
+			eval("@data[&file := team.json;]", &ExpandOptions::default()),
+			Err(TeaCatError::InvalidData(Pos { line: 0, .. }, _))
+		));
+
+		let absolute = dir.join("team.json");
+		let str = format!("@data[&file := {};]", absolute.display());
+		assert!(matches!(
+			eval(&str, &options),
+			Err(TeaCatError::InvalidData(Pos { line: 0, .. }, _))
+		));
+	}
+
+	#[test]
+	fn each() {
+		let json = serde_json::json!({
+			"team": [{ "name": "Juni", "role": "Admin" }, { "name": "Sam" }],
+			"pets": ["Cat", "Dog"],
+		});
+		let options = ExpandOptions {
+			vars: crate::data::data_vars("site", &json),
+			..ExpandOptions::default()
+		};
+		let eval = |str: &str| {
+			let mut diagnostics = Diagnostics::new();
+			let html =
+				eval_teacat_string_with::<HtmlRenderer, String>(str, &options, &mut diagnostics);
+			(html, diagnostics.0)
+		};
+
+		// Unused fields are not warned about
+		let (html, diagnostics) = eval(
+			"macr @member{&name}[:li[&name]]\n:ul[@each[&list := site.team; &apply := member;]]",
+		);
+		assert_eq!(
+			html.unwrap(),
+			"<!DOCTYPE html><html><ul><li>Juni</li><li>Sam</li></ul></html>"
+		);
+		assert!(diagnostics.is_empty());
+
+		// Items that are not objects are passed as '&item', and lists within the TeaCat string
+		// replace predefined ones
+		let (html, _) = eval(
+			"macr @pet{&item}[:i[&item]]\n@each[&list := site.pets; &apply := pet;]\n&site.pets.1 := Fish;\n@each[&list := site.pets; &apply := pet;]",
+		);
+		assert_eq!(
+			html.unwrap(),
+			"<!DOCTYPE html><html><i>Cat</i><i>Dog</i><i>Cat</i><i>Fish</i></html>"
+		);
+
+		// Lists defined within the TeaCat string are passed item by item
+		let (html, diagnostics) =
+			eval("&l := {a;b;};\nmacr @m{&item}[:li[&item]]\n@each[&list := l; &apply := m;]");
+		assert_eq!(
+			html.unwrap(),
+			"<!DOCTYPE html><html><li>a</li><li>b</li></html>"
+		);
+		assert!(diagnostics.is_empty());
+
+		// Macros named 'each' are used instead
+		let (html, _) = eval("macr @each{&list}[&list]\n@each[&list := x;]");
+		assert_eq!(html.unwrap(), "<!DOCTYPE html><html>x</html>");
+
+		for str in [
+			"macr @m{}[]\n@each[&list := site.team;]",
+			"macr @m{}[]\n@each[&apply := m;]",
+			"macr @m{}[]\n@each[&list := site.team.0.name; &apply := m;]",
+			"&l := a; macr @m{}[]\n@each[&list := l; &apply := m;]",
+			"macr @m{}[]\n@each[&list := site.team; &apply := m; &other := x;]",
+		] {
+			assert!(matches!(
+				eval(str).0,
+				Err(TeaCatError::InvalidEach(Pos { line: 1, .. }, _))
+			));
+		}
+		assert!(matches!(
+			eval("macr @member{}[]\n@each[&list := site.teem; &apply := member;]").0,
+			Err(TeaCatError::UndefinedVar(Pos { line: 1, .. }, _, Some((Defined::Predefined, similar)))) if similar == "site.team"
+		));
+		assert!(matches!(
+			eval("macr @member{}[]\n@each[&list := site.team; &apply := membr;]").0,
+			Err(TeaCatError::UndefinedMacr(
+				Pos { line: 1, .. },
+				_,
+				Some((Pos { line: 0, .. }, _))
+			))
+		));
 	}
 }
@@ -0,0 +1,333 @@
+use html_escape::decode_html_entities;
+
+use super::renderer::html::VOID;
+use crate::{
+	lexer::{StringLiteral, str_walker::StrWalker},
+	prelude::*,
+};
+
+/// Elements whose contents are text, rather than HTML.
+const RAW_TEXT: &[&str] = &["script", "style"];
+/// Elements whose `li` children can be converted into an [Array](ExpandedNode::Array).
+const LISTS: &[&str] = &["ul", "ol"];
+
+impl ExpandedAst {
+	/// Parses an HTML string into an [ExpandedAst], allowing it to be rendered into another
+	/// form (such as TeaCat through the [TeaCatRenderer]).
+	///
+	/// Parsing is lenient: unclosed tags are closed alongside their parent, and closing
+	/// tags without a matching opening tag are ignored. Comments and doctypes are removed,
+	/// and the `html` tag itself is unwrapped, as the [HtmlRenderer] adds it back.
+	/// # Examples
+	/// ```
+	/// use teacat_lib::prelude::*;
+	///
+	/// let expanded = ExpandedAst::from_html("<!DOCTYPE html><html><p class='x'>Hello<br></html>");
+	///
+	/// assert_eq!(TeaCatRenderer::render(expanded), ":p{class:\"x\"}[Hello:br;]");
+	/// ```
+	pub fn from_html(html: impl AsRef<str>) -> Self {
+		let mut walker = StrWalker::new(html.as_ref());
+		// The root is an unnamed tag that is never closed
+		let mut stack = vec![ExpandedTag::new("")];
+
+		while !walker.reached_end() {
+			if walker.currently_starts_with("<!--") {
+				skip_past(&mut walker, "-->");
+			} else if walker.currently_starts_with("<!") || walker.currently_starts_with("<?") {
+				skip_past(&mut walker, ">");
+			} else if walker.currently_starts_with("</") {
+				walker.jump_by("</".len());
+				let name = name(&mut walker);
+				skip_past(&mut walker, ">");
+
+				// Only tags that are actually open can be closed
+				if let Some(i) = stack.iter().skip(1).rposition(|tag| tag.name == name) {
+					close_until(&mut stack, i + 1);
+				}
+			} else if walker.currently_starts_with("<")
+				&& walker
+					.peek_nth(1)
+					.is_some_and(|ch| ch.is_ascii_alphabetic())
+			{
+				walker.next_char();
+				open_tag(&mut walker, &mut stack);
+			} else {
+				let start = walker.index();
+				walker.next_char();
+				while walker.peek_char().is_some_and(|ch| ch != '<') {
+					walker.next_char();
+				}
+
+				let text = decode_html_entities(walker.since(start)).to_string();
+				stack
+					.last_mut()
+					.expect("The root should never be closed")
+					.push(ExpandedNode::Text(text));
+			}
+		}
+
+		close_until(&mut stack, 1);
+		let mut root = stack.pop().expect("The root should never be closed");
+		root.contents.clean_text();
+
+		match root.contents.0.as_slice() {
+			[ExpandedNode::Tag(tag)] if tag.name == "html" => tag.contents.clone(),
+			_ => root.contents,
+		}
+	}
+
+	/// Converts any sequences of whitespace within text into singular spaces, and removes any
+	/// text consisting only of whitespace.
+	fn clean_text(&mut self) {
+		self.0
+			.retain(|node| !matches!(node, ExpandedNode::Text(text) if text.trim().is_empty()));
+
+		for node in &mut self.0 {
+			if let ExpandedNode::Text(text) = node {
+				let starts_with = text.starts_with(char::is_whitespace);
+				let ends_with = text.ends_with(char::is_whitespace);
+
+				*text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+				if starts_with {
+					text.insert(0, ' ');
+				}
+				if ends_with {
+					text.push(' ');
+				}
+			}
+		}
+	}
+}
+
+impl ExpandedTag {
+	fn new(name: impl Into<String>) -> Self {
+		Self {
+			name: name.into(),
+			attributes: Attributes::new(),
+			contents: ExpandedAst(vec![]),
+		}
+	}
+
+	fn push(&mut self, node: ExpandedNode) {
+		self.contents.0.push(node);
+	}
+
+	/// Converts the contents of a list into an [Array](ExpandedNode::Array), if it only contains
+	/// `li` tags without any attributes.
+	fn list_to_array(&mut self) {
+		let all_items = self.contents.0.iter().all(
+			|node| matches!(node, ExpandedNode::Tag(tag) if tag.name == "li" && tag.attributes.0.is_empty()),
+		);
+		if !LISTS.contains(&self.name.as_str()) || self.contents.0.is_empty() || !all_items {
+			return;
+		}
+
+		let array = self
+			.contents
+			.0
+			.drain(..)
+			.map(|node| match node {
+				ExpandedNode::Tag(tag) => tag.contents,
+				_ => unreachable!(),
+			})
+			.collect();
+
+		self.push(ExpandedNode::Array(array));
+	}
+}
+
+/// Parses an opening tag (after the '<'), and pushes it onto the stack if it can have contents.
+fn open_tag(walker: &mut StrWalker, stack: &mut Vec<ExpandedTag>) {
+	let mut tag = ExpandedTag::new(name(walker));
+	let mut self_closing = false;
+
+	loop {
+		skip_whitespace(walker);
+
+		if walker.currently_starts_with("/>") {
+			walker.jump_by("/>".len());
+			self_closing = true;
+			break;
+		}
+		match walker.peek_char() {
+			None => break,
+			Some('>') => {
+				walker.next_char();
+				break;
+			}
+			Some('/') => {
+				walker.next_char();
+				continue;
+			}
+			_ => (),
+		}
+
+		let key = name(walker);
+		skip_whitespace(walker);
+
+		let val = if walker.peek_char() == Some('=') {
+			walker.next_char();
+			skip_whitespace(walker);
+			attribute_val(walker)
+		} else {
+			String::new()
+		};
+
+		// Only the first instance of an attribute is used
+		tag.attributes
+			.0
+			.entry(key)
+			.or_insert_with(|| StringLiteral::new(if val.contains('"') { '\'' } else { '"' }, val));
+	}
+
+	// Some tags are implicitly closed by opening another of the same kind
+	if ["li", "p"].contains(&tag.name.as_str())
+		&& stack.last().is_some_and(|open| open.name == tag.name)
+	{
+		close_until(stack, stack.len() - 1);
+	}
+
+	if self_closing || VOID.contains(&tag.name.as_str()) {
+		stack
+			.last_mut()
+			.expect("The root should never be closed")
+			.push(ExpandedNode::Tag(tag));
+		return;
+	}
+
+	if RAW_TEXT.contains(&tag.name.as_str()) {
+		let start = walker.index();
+		let end = format!("</{}", tag.name);
+		while !walker.reached_end() && !walker.currently_starts_with(&end) {
+			walker.next_char();
+		}
+
+		let text = walker.since(start);
+		if !text.is_empty() {
+			tag.push(ExpandedNode::Text(text.into()));
+		}
+
+		skip_past(walker, ">");
+		stack
+			.last_mut()
+			.expect("The root should never be closed")
+			.push(ExpandedNode::Tag(tag));
+		return;
+	}
+
+	stack.push(tag);
+}
+
+/// Closes every tag on the stack from the provided index onwards, adding each to its parent.
+fn close_until(stack: &mut Vec<ExpandedTag>, index: usize) {
+	while stack.len() > index {
+		let mut tag = stack.pop().expect("Stack should not be empty");
+		tag.contents.clean_text();
+		tag.list_to_array();
+
+		stack
+			.last_mut()
+			.expect("The root should never be closed")
+			.push(ExpandedNode::Tag(tag));
+	}
+}
+
+/// Gets the name of a tag or attribute, in lowercase.
+fn name(walker: &mut StrWalker) -> String {
+	let start = walker.index();
+	while walker
+		.peek_char()
+		.is_some_and(|ch| !ch.is_whitespace() && !['/', '>', '='].contains(&ch))
+	{
+		walker.next_char();
+	}
+
+	walker.since(start).to_lowercase()
+}
+
+/// Gets the value of an attribute, which may or may not be quoted.
+fn attribute_val(walker: &mut StrWalker) -> String {
+	let quotes = walker.peek_char().filter(|ch| ['"', '\''].contains(ch));
+	if quotes.is_some() {
+		walker.next_char();
+	}
+
+	let start = walker.index();
+	while walker.peek_char().is_some_and(|ch| match quotes {
+		Some(quotes) => ch != quotes,
+		None => !ch.is_whitespace() && ch != '>',
+	}) {
+		walker.next_char();
+	}
+	let val = walker.since(start).to_string();
+
+	if quotes.is_some() {
+		walker.next_char();
+	}
+	val
+}
+
+/// Walks past the next instance of the target.
+fn skip_past(walker: &mut StrWalker, target: &str) {
+	while !walker.reached_end() && !walker.currently_starts_with(target) {
+		walker.next_char();
+	}
+	if !walker.reached_end() {
+		walker.jump_by(target.len());
+	}
+}
+
+fn skip_whitespace(walker: &mut StrWalker) {
+	while walker.peek_char().is_some_and(char::is_whitespace) {
+		walker.next_char();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn round_trip(html: &str) -> String {
+		let teacat = TeaCatRenderer::render(ExpandedAst::from_html(html));
+		eval_teacat_string::<HtmlRenderer, _>(teacat).unwrap()
+	}
+
+	#[test]
+	fn tags() {
+		assert_eq!(
+			TeaCatRenderer::render(ExpandedAst::from_html(
+				"<html>\n\t<head><title>Hi</title></head>\n\t<body><p>Hello <b>World</b></p></body>\n</html>"
+			)),
+			":head[:title[Hi]]:body[:p[Hello :b[World]]]"
+		);
+	}
+
+	#[test]
+	fn lists() {
+		assert_eq!(
+			TeaCatRenderer::render(ExpandedAst::from_html(
+				"<ul><li>One<li>Two</li></ul><ol><li class='x'>Three</li></ol>"
+			)),
+			":ul[{One;Two;}]:ol[:li{class:\"x\"}[Three]]"
+		);
+	}
+
+	#[test]
+	fn escaping() {
+		assert_eq!(
+			TeaCatRenderer::render(ExpandedAst::from_html(
+				"<p title='say \"hi\"'>&lt;3 [a] {b} &amp;c @d</p>"
+			)),
+			":p{title:'say \"hi\"'}[<3 \\[a\\] \\{b\\} \\&c \\@d]"
+		);
+	}
+
+	#[test]
+	fn round_trips() {
+		let html = "<!DOCTYPE html><html><head><title>My Webpage</title></head><body><p>&amp;title<br></br><img alt=\"Test Image\" src=\"a.png\"></img><ul><li>Hello</li><li><h2>Tags can be placed within arrays too</h2></li></ul></p></body></html>";
+
+		assert_eq!(round_trip(html), html);
+	}
+}
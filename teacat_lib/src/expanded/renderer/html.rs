@@ -1,8 +1,25 @@
 use crate::prelude::*;
 
+/// Elements which cannot have any contents.
+pub const VOID: &[&str] = &[
+	"area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+	"wbr",
+];
+
+/// Elements whose whitespace is shown, so their contents are never indented.
+const PREFORMATTED: &[&str] = &["pre", "textarea", "script", "style"];
+
 /// Renders an [ExpandedAst] into an HTML string.
 pub struct HtmlRenderer;
 
+/// Renders an [ExpandedAst] into an HTML string in the same way as [HtmlRenderer], except that
+/// elements only containing other elements have each of them on its own line, indented with
+/// tabs. Elements that also contain text are left on one line, so that no whitespace is added
+/// within the text.
+pub struct PrettyHtmlRenderer {
+	depth: usize,
+}
+
 impl Renderer<String> for HtmlRenderer {
 	fn render(ast: ExpandedAst) -> String {
 		let mut renderer = Self;
@@ -55,3 +72,95 @@ impl Renderer<String> for HtmlRenderer {
 			.join("")
 	}
 }
+
+impl Renderer<String> for PrettyHtmlRenderer {
+	fn render(ast: ExpandedAst) -> String {
+		let mut renderer = Self { depth: 0 };
+		format!("<!DOCTYPE html>\n<html>{}</html>", renderer.render_ast(ast))
+	}
+
+	fn render_ast(&mut self, ast: ExpandedAst) -> String {
+		let has_text = ast
+			.0
+			.iter()
+			.any(|node| matches!(node, ExpandedNode::Text(_)));
+		if has_text || ast.0.is_empty() {
+			return HtmlRenderer.render_ast(ast);
+		}
+
+		let mut rendered = String::new();
+		self.depth += 1;
+
+		for node in ast.0 {
+			rendered.push_str(&self.newline());
+			rendered.push_str(&match node {
+				ExpandedNode::Tag(tag) => self.render_tag(tag),
+				ExpandedNode::Array(array) => self.render_array(array),
+				ExpandedNode::Text(text) => self.render_text(text),
+			});
+		}
+
+		self.depth -= 1;
+		rendered + &self.newline()
+	}
+
+	fn render_tag(&mut self, tag: ExpandedTag) -> String {
+		if PREFORMATTED.contains(&tag.name.as_str()) {
+			return HtmlRenderer.render_tag(tag);
+		}
+
+		format!(
+			"<{}{}>{}</{}>",
+			tag.name,
+			self.render_attributes(tag.attributes),
+			self.render_ast(tag.contents),
+			tag.name
+		)
+	}
+
+	fn render_text(&mut self, text: String) -> String {
+		HtmlRenderer.render_text(text)
+	}
+
+	fn render_attributes(&mut self, attributes: Attributes) -> String {
+		HtmlRenderer.render_attributes(attributes)
+	}
+
+	fn render_array(&mut self, array: Vec<ExpandedAst>) -> String {
+		array
+			.into_iter()
+			.map(|ast| format!("<li>{}</li>", self.render_ast(ast)))
+			.collect::<Vec<String>>()
+			.join(&self.newline())
+	}
+}
+
+impl PrettyHtmlRenderer {
+	/// A line break, followed by the indentation of the current depth.
+	fn newline(&self) -> String {
+		format!("\n{}", "\t".repeat(self.depth))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn pretty() {
+		let render = |str| {
+			let ast = Ast::parse(TokenStream::lex(str).unwrap()).unwrap();
+			PrettyHtmlRenderer::render(ExpandedAst::expand(ast).unwrap())
+		};
+
+		assert_eq!(
+			render(":body[:main[:h1[Hi] :p[Some :b[bold] text]] :pre[:code[x]]]"),
+			"<!DOCTYPE html>\n<html>\n\t<body>\n\t\t<main>\n\t\t\t<h1>Hi</h1>\n\t\t\t<p>Some <b>bold</b> text</p>\n\t\t</main>\n\t\t<pre><code>x</code></pre>\n\t</body>\n</html>"
+		);
+		assert_eq!(
+			render(":ul[{:b[a];b;}]"),
+			"<!DOCTYPE html>\n<html>\n\t<ul>\n\t\t<li>\n\t\t\t<b>a</b>\n\t\t</li>\n\t\t<li>b</li>\n\t</ul>\n</html>"
+		);
+		assert_eq!(render("Hi"), "<!DOCTYPE html>\n<html>Hi</html>");
+	}
+}
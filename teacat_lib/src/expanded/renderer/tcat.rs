@@ -1,5 +1,6 @@
 use crate::prelude::*;
 
+/// Renders an [ExpandedAst] into a TeaCat string.
 pub struct TeaCatRenderer;
 
 impl Renderer<String> for TeaCatRenderer {
@@ -22,25 +23,35 @@ impl Renderer<String> for TeaCatRenderer {
 	}
 
 	fn render_tag(&mut self, tag: ExpandedTag) -> String {
-		format!(
-			":{}{}[{}]",
-			tag.name,
-			self.render_attributes(tag.attributes),
-			self.render_ast(tag.contents)
-		)
+		let attributes = self.render_attributes(tag.attributes);
+
+		if tag.contents.0.is_empty() {
+			format!(":{}{attributes};", tag.name)
+		} else {
+			format!(
+				":{}{attributes}[{}]",
+				tag.name,
+				self.render_ast(tag.contents)
+			)
+		}
 	}
 
 	fn render_text(&mut self, text: String) -> String {
-		encode_str(text) 
+		encode_str(text)
 	}
 
 	fn render_attributes(&mut self, attributes: Attributes) -> String {
-		let mut rendered = String::new();
-
-		for (key, val) in attributes.0 {
-			rendered.push_str(&format!("{key}:{} ", val.into_string()));
+		if attributes.0.is_empty() {
+			return String::new();
 		}
 
+		let rendered = attributes
+			.0
+			.into_iter()
+			.map(|(key, val)| format!("{}:{}", encode_str(key), val.encode()))
+			.collect::<Vec<String>>()
+			.join(" ");
+
 		surround_curly(rendered)
 	}
 
@@ -58,3 +69,99 @@ impl Renderer<String> for TeaCatRenderer {
 fn surround_curly(str: String) -> String {
 	format!("{{{str}}}")
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::lexer::StringLiteral;
+
+	/// A small xorshift generator, so that generated [ExpandedAsts](ExpandedAst) are reproducible.
+	struct Rng(u64);
+
+	impl Rng {
+		fn next(&mut self, max: usize) -> usize {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			(self.0 % max as u64) as usize
+		}
+
+		fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+			&items[self.next(items.len())]
+		}
+
+		fn string(&mut self) -> String {
+			const CHARS: &[&str] = &[
+				"a", "b", "macr", "m", "\\", "[", "]", "{", "}", ":", ":=", "=", ";", "&", "@",
+				"#", "<#", "#>", "<", "'", "\"", " ", "  ", "\n", "\t", "\r", "\u{a0}", "é",
+			];
+
+			(0..self.next(8)).map(|_| *self.pick(CHARS)).collect()
+		}
+
+		fn ast(&mut self, depth: usize) -> ExpandedAst {
+			ExpandedAst((0..self.next(4)).map(|_| self.node(depth)).collect())
+		}
+
+		fn node(&mut self, depth: usize) -> ExpandedNode {
+			match self.next(if depth == 0 { 1 } else { 4 }) {
+				0 => ExpandedNode::Text(self.string()),
+				1 => ExpandedNode::Array((0..self.next(3)).map(|_| self.ast(depth - 1)).collect()),
+				_ => {
+					let mut attributes = Attributes::new();
+					for _ in 0..self.next(3) {
+						let key = self.pick(&["id", "class", "data-x", "src"]).to_string();
+						let quotes = *self.pick(&['"', '\'']);
+						attributes
+							.0
+							.insert(key, StringLiteral::new(quotes, self.string()));
+					}
+
+					ExpandedNode::Tag(ExpandedTag {
+						name: self.pick(&["a", "p", "div", "h1"]).to_string(),
+						attributes,
+						contents: self.ast(depth - 1),
+					})
+				}
+			}
+		}
+	}
+
+	/// Merges neighbouring text and removes empty text, as they are indistinguishable once rendered.
+	fn normalize(ast: ExpandedAst) -> ExpandedAst {
+		let mut nodes: Vec<ExpandedNode> = vec![];
+
+		for node in ast.0 {
+			match (nodes.last_mut(), node) {
+				(_, ExpandedNode::Text(text)) if text.is_empty() => (),
+				(Some(ExpandedNode::Text(prev)), ExpandedNode::Text(text)) => prev.push_str(&text),
+
+				(_, ExpandedNode::Tag(mut tag)) => {
+					tag.contents = normalize(tag.contents);
+					nodes.push(ExpandedNode::Tag(tag));
+				}
+				(_, ExpandedNode::Array(array)) => nodes.push(ExpandedNode::Array(
+					array.into_iter().map(normalize).collect(),
+				)),
+				(_, other) => nodes.push(other),
+			}
+		}
+
+		ExpandedAst(nodes)
+	}
+
+	#[test]
+	fn round_trip() {
+		let mut rng = Rng(0x7eaca7);
+
+		for _ in 0..2000 {
+			let ast = rng.ast(3);
+			let rendered = TeaCatRenderer::render(ast.clone());
+
+			let tokenstream = TokenStream::lex(&rendered).unwrap();
+			let expanded = ExpandedAst::expand(Ast::parse(tokenstream).unwrap()).unwrap();
+
+			assert_eq!(normalize(expanded), normalize(ast), "{rendered:?}");
+		}
+	}
+}
@@ -0,0 +1,604 @@
+use std::ops::Range;
+
+use crate::{
+	front_matter::{parse_front_matter, split_front_matter},
+	lexer::{
+		Token,
+		cst::{CstToken, Trivia},
+	},
+	prelude::*,
+};
+
+/// Formats a TeaCat string, keeping all variables, macros and comments intact.
+///
+/// Block content is indented with tabs and has one tag per line, and the spacing around
+/// attributes and other operators is made consistent. Whitespace that gives text a leading or
+/// trailing space is kept, and any front matter is kept as it is.
+/// # Errors
+/// Returns an [Error](TeaCatError) if the string cannot be lexed or parsed, or an
+/// [InternalFormatter](TeaCatError::InternalFormatter) error if formatting would have changed
+/// what it means.
+/// # Examples
+/// ```
+/// use teacat_lib::prelude::*;
+///
+/// assert_eq!(
+/// 	format_str(":head[  :title [Hi]   ]\n:body[\n:p{ class:'x'  id:'y' }[Hello]:br;]").unwrap(),
+/// 	":head[:title[Hi]]\n:body[\n\t:p{class:'x' id:'y'}[Hello]\n\t:br;\n]\n",
+/// );
+/// ```
+pub fn format_str(teacat_str: impl AsRef<str>) -> CatResult<String> {
+	let teacat_str = teacat_str.as_ref();
+	// Only valid files are formatted
+	let (_, body) = parse_front_matter(teacat_str)?;
+	Ast::parse(TokenStream::lex(body)?)?;
+
+	let (front_matter, body) = split_front_matter(teacat_str)?;
+	let cst = Cst::lex(body)?;
+	let formatted = Formatter::new(&cst).format();
+
+	// The formatter should never change what a file means, so if it somehow did, nothing is
+	// written rather than the file being broken
+	let offset = front_matter.matches('\n').count();
+	check_meaning(body, &formatted, offset)?;
+	Ok(front_matter.to_string() + &formatted)
+}
+
+/// Checks that a formatted string means the same as the original, with `offset` being the
+/// number of lines before the original (such as front matter).
+/// # Errors
+/// Returns an [InternalFormatter](TeaCatError::InternalFormatter) error with the line of the
+/// original string where they first differ, if the formatted string can be lexed.
+fn check_meaning(original: &str, formatted: &str, offset: usize) -> CatResult<()> {
+	let original = meaning(original)?;
+	let Ok(formatted) = meaning(formatted) else {
+		return Err(TeaCatError::InternalFormatter(None));
+	};
+
+	let differs = (original.iter().zip(&formatted))
+		.position(|((_, a), (_, b))| a != b)
+		.or_else(|| {
+			(original.len() != formatted.len()).then(|| original.len().min(formatted.len()))
+		});
+	let Some(index) = differs else {
+		return Ok(());
+	};
+
+	// Tokens that were only added at the end are after the last line of the original
+	let line = (original.get(index))
+		.or(original.last())
+		.map_or(0, |(pos, _)| pos.line);
+	Err(TeaCatError::InternalFormatter(Some(line + offset)))
+}
+
+/// Formats a [Cst] into a [String].
+struct Formatter<'cst> {
+	cst: &'cst Cst,
+	/// For each [Token], the group it opens (if any).
+	groups: Vec<Option<Group>>,
+	/// The groups that the current [Token] is within.
+	stack: Vec<Group>,
+	out: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Group {
+	close: usize,
+	/// Whether or not the group's contents span multiple lines.
+	block: bool,
+	/// Whether or not the group contains the attributes of a tag.
+	attributes: bool,
+}
+
+impl<'cst> Formatter<'cst> {
+	fn new(cst: &'cst Cst) -> Self {
+		Self {
+			cst,
+			groups: find_groups(&cst.tokens),
+			stack: vec![],
+			out: String::new(),
+		}
+	}
+
+	fn format(mut self) -> String {
+		let tokens = &self.cst.tokens;
+
+		for (i, cst_token) in tokens.iter().enumerate() {
+			let prev = i.checked_sub(1).map(|i| &tokens[i]);
+			self.gap(&cst_token.leading, prev, Some(cst_token), i);
+
+			if self.stack.last().is_some_and(|group| group.close == i) {
+				self.stack.pop();
+			}
+
+			self.out.push_str(match cst_token.token {
+				Token::Macr => "macr",
+				Token::Walrus => ":=",
+				_ => &cst_token.source,
+			});
+
+			if let Some(group) = self.groups[i] {
+				self.stack.push(group);
+			}
+		}
+
+		self.gap(&self.cst.trailing, tokens.last(), None, tokens.len());
+		self.out
+	}
+
+	/// Writes the space between two [Tokens](Token), including any comments.
+	fn gap(
+		&mut self,
+		trivia: &[Trivia],
+		prev: Option<&CstToken>,
+		next: Option<&CstToken>,
+		index: usize,
+	) {
+		let start = self.out.len();
+		let mut comments = vec![];
+		self.write_gap(trivia, prev, next, index, &mut comments);
+
+		let attributes = self.stack.last().is_some_and(|group| group.attributes);
+		if !attributes {
+			self.keep_text_edges(start, &comments, trivia, prev, next);
+		}
+	}
+
+	/// Writes the space between two [Tokens](Token), adding the range of each comment written
+	/// to `comments`.
+	fn write_gap(
+		&mut self,
+		trivia: &[Trivia],
+		prev: Option<&CstToken>,
+		next: Option<&CstToken>,
+		index: usize,
+		comments: &mut Vec<Range<usize>>,
+	) {
+		let prev_token = prev.map(|cst_token| &cst_token.token);
+		let next_token = next.map(|cst_token| &cst_token.token);
+
+		let closes = self.stack.last().is_some_and(|group| group.close == index);
+		let attributes = self.stack.last().is_some_and(|group| group.attributes);
+		// Whitespace next to text is kept as part of the text, so only its presence can be changed
+		let sensitive = !attributes
+			&& (matches!(prev_token, Some(Token::Text(_)))
+				|| matches!(next_token, Some(Token::Text(_))));
+
+		let mut newlines = 0;
+		let mut whitespace = false;
+		let mut line_comment = false;
+
+		for trivia in trivia {
+			match trivia {
+				Trivia::Whitespace(str) => {
+					newlines += str.matches('\n').count();
+					whitespace = true;
+				}
+				Trivia::Comment(str) | Trivia::MultiComment(str) => {
+					// Comments at the start of the file are not moved
+					if newlines > 0 || line_comment {
+						if !self.out.is_empty() {
+							self.newlines(newlines, self.indent(false));
+						}
+					} else if !self.out.is_empty() && (whitespace || !sensitive) {
+						// Comments touching text are kept touching it, as a space would be
+						// added to the text
+						self.out.push(' ');
+					}
+
+					let start = self.out.len();
+					self.out.push_str(str.trim_end());
+					comments.push(start..self.out.len());
+					line_comment = matches!(trivia, Trivia::Comment(_));
+					newlines = 0;
+					whitespace = false;
+				}
+			}
+		}
+
+		if next.is_none() {
+			if !self.out.is_empty() {
+				self.newlines(0, 0);
+			}
+			return;
+		}
+		if prev.is_none() {
+			if line_comment || newlines > 0 && !self.out.is_empty() {
+				self.newlines(newlines, 0);
+			}
+			return;
+		}
+
+		let newline = line_comment
+			|| newlines > 0
+			|| !sensitive
+				&& (self.opens_block(index - 1)
+					|| closes && self.in_block()
+					|| self.in_block()
+						&& ends_element(prev_token)
+						&& starts_element(index, self.cst));
+
+		if newline {
+			let newlines = if self.opens_block(index - 1) || closes {
+				1
+			} else {
+				newlines
+			};
+			self.newlines(newlines, self.indent(closes));
+			return;
+		}
+
+		let space = match (prev_token, next_token) {
+			(Some(Token::Walrus), _) => true,
+			(_, Some(Token::Walrus)) => true,
+			(Some(Token::Macr), _) => true,
+			(Some(Token::Stringliteral(_)), Some(Token::Text(_))) if attributes => true,
+
+			_ if sensitive => whitespace,
+
+			(Some(Token::Colon | Token::Andpersand | Token::At), _) => false,
+			(Some(Token::OpenBracket | Token::OpenBrace), _) => false,
+			(
+				_,
+				Some(
+					Token::OpenBracket
+					| Token::CloseBracket
+					| Token::OpenBrace
+					| Token::CloseBrace
+					| Token::SemiColon,
+				),
+			) => false,
+
+			_ => whitespace,
+		};
+
+		if space {
+			self.out.push(' ');
+		}
+		// ':= ' consumes a single space, so any other whitespace is part of the text after it
+		if matches!(prev_token, Some(Token::Walrus))
+			&& prev.is_some_and(|prev| prev.source.ends_with(' '))
+			&& whitespace
+			&& sensitive
+		{
+			self.out.push(' ');
+		}
+	}
+
+	/// Makes the whitespace written since `start` begin (or end) in the same way as the
+	/// original, where it is at the start (or end) of text. Text only gains a leading or
+	/// trailing space if it starts or ends with whitespace other than a newline or tab.
+	fn keep_text_edges(
+		&mut self,
+		start: usize,
+		comments: &[Range<usize>],
+		trivia: &[Trivia],
+		prev: Option<&CstToken>,
+		next: Option<&CstToken>,
+	) {
+		let is_text = |cst_token: Option<&CstToken>| {
+			cst_token.is_some_and(|cst_token| matches!(cst_token.token, Token::Text(_)))
+		};
+		let leading = !is_text(prev) && is_text(next);
+		let trailing = is_text(prev) && !is_text(next);
+		if !leading && !trailing {
+			return;
+		}
+
+		let original: String = (trivia.iter())
+			.filter_map(|trivia| match trivia {
+				Trivia::Whitespace(str) => Some(str.as_str()),
+				_ => None,
+			})
+			.collect();
+		// ':= ' consumes a single space, so it is not part of the text after it
+		let walrus = prev.is_some_and(|prev| prev.token == Token::Walrus);
+		let consumed = walrus && self.out[start..].starts_with(' ');
+		let written: Vec<(usize, char)> = (self.out[start..].char_indices())
+			.map(|(i, ch)| (start + i, ch))
+			.filter(|(i, _)| !comments.iter().any(|comment| comment.contains(i)))
+			.skip(usize::from(consumed))
+			.collect();
+
+		let (want, have) = if leading {
+			(original.chars().next(), written.first())
+		} else {
+			(original.chars().last(), written.last())
+		};
+		let want_space = want.is_some_and(spaces_text);
+
+		match have {
+			Some(&(i, ch)) if spaces_text(ch) && !want_space => {
+				let replacement = if want.is_some() { "\t" } else { "" };
+				self.out.replace_range(i..i + ch.len_utf8(), replacement);
+			}
+			_ if want_space && !have.is_some_and(|&(_, ch)| spaces_text(ch)) => {
+				if trailing {
+					self.out.push(' ');
+				} else if consumed || !walrus {
+					self.out.insert(start + usize::from(consumed), ' ');
+				} else {
+					self.out.insert_str(start, "  ");
+				}
+			}
+			_ => {}
+		}
+	}
+
+	/// Ends the current line, keeping at most one blank line, and indents the next one.
+	fn newlines(&mut self, newlines: usize, indent: usize) {
+		self.out.push_str(if newlines > 1 { "\n\n" } else { "\n" });
+		self.out.push_str(&"\t".repeat(indent));
+	}
+
+	fn indent(&self, closes: bool) -> usize {
+		let indent = self.stack.iter().filter(|group| group.block).count();
+
+		if closes && self.in_block() {
+			indent - 1
+		} else {
+			indent
+		}
+	}
+
+	fn in_block(&self) -> bool {
+		self.stack.last().is_none_or(|group| group.block)
+	}
+
+	fn opens_block(&self, index: usize) -> bool {
+		self.groups[index].is_some_and(|group| group.block)
+	}
+}
+
+/// Matches each opening bracket/brace with its closing counterpart, and checks whether
+/// or not its contents span multiple lines.
+fn find_groups(tokens: &[CstToken]) -> Vec<Option<Group>> {
+	let mut groups = vec![None; tokens.len()];
+	let mut open: Vec<(usize, Token)> = vec![];
+
+	for (i, cst_token) in tokens.iter().enumerate() {
+		let close = match cst_token.token {
+			Token::OpenBracket => Token::CloseBracket,
+			Token::OpenBrace => Token::CloseBrace,
+
+			ref token if open.last().is_some_and(|(_, close)| close == token) => {
+				let (start, _) = open.pop().expect("Should have an opening token");
+				groups[start] = Some(Group {
+					close: i,
+					block: is_block(tokens, &groups, start, i),
+					attributes: is_attributes(tokens, start),
+				});
+				continue;
+			}
+			_ => continue,
+		};
+
+		open.push((i, close));
+	}
+
+	// Unclosed groups are treated as never ending
+	for (start, _) in open.into_iter().rev() {
+		groups[start] = Some(Group {
+			close: usize::MAX,
+			block: is_block(tokens, &groups, start, tokens.len()),
+			attributes: is_attributes(tokens, start),
+		});
+	}
+
+	groups
+}
+
+/// Checks whether or not any of the top-level gaps within a group contain newlines or comments.
+fn is_block(tokens: &[CstToken], groups: &[Option<Group>], start: usize, end: usize) -> bool {
+	let mut i = start + 1;
+
+	while i <= end {
+		let Some(cst_token) = tokens.get(i) else {
+			break;
+		};
+
+		let multiline = cst_token.leading.iter().any(|trivia| match trivia {
+			Trivia::Whitespace(str) => str.contains('\n'),
+			_ => true,
+		});
+		if multiline {
+			return true;
+		}
+
+		// Skipping past nested groups
+		i = match groups[i] {
+			Some(group) => group.close.saturating_add(1),
+			None => i + 1,
+		};
+	}
+
+	false
+}
+
+/// Checks whether or not a group contains the attributes of a tag.
+fn is_attributes(tokens: &[CstToken], start: usize) -> bool {
+	let token = |i: Option<usize>| {
+		i.and_then(|i| tokens.get(i))
+			.map(|cst_token| &cst_token.token)
+	};
+
+	matches!(token(Some(start)), Some(Token::OpenBrace))
+		&& matches!(token(start.checked_sub(1)), Some(Token::Ident(_)))
+		&& matches!(token(start.checked_sub(2)), Some(Token::Colon))
+}
+
+/// Whether or not whitespace at the edge of text gives it a leading or trailing space, which
+/// is the case for any whitespace other than newlines and tabs.
+fn spaces_text(ch: char) -> bool {
+	ch.is_whitespace() && !['\n', '\t'].contains(&ch)
+}
+
+/// Whether or not the [Token] can end a tag or variable.
+fn ends_element(token: Option<&Token>) -> bool {
+	matches!(token, Some(Token::CloseBracket | Token::SemiColon))
+}
+
+/// Whether or not the [Token] at the index starts a tag, macro or variable definition.
+fn starts_element(index: usize, cst: &Cst) -> bool {
+	let token = |i: usize| cst.tokens.get(i).map(|cst_token| &cst_token.token);
+
+	match token(index) {
+		Some(Token::Colon | Token::Macr | Token::At) => true,
+		Some(Token::Andpersand) => matches!(token(index + 2), Some(Token::Walrus)),
+		_ => false,
+	}
+}
+
+/// Gets the [Tokens](Token) of a TeaCat string alongside their positions, ignoring whitespace
+/// around the keys of attributes (which is removed when parsing).
+fn meaning(teacat_str: &str) -> CatResult<Vec<(Pos, Token)>> {
+	let mut tokens: Vec<(Pos, Token)> = TokenStream::lex(teacat_str)?.0.into();
+	let mut attributes = false;
+
+	for i in 0..tokens.len() {
+		let token = |i: Option<usize>| i.and_then(|i| tokens.get(i)).map(|(_, token)| token);
+
+		match &tokens[i].1 {
+			Token::OpenBrace => {
+				attributes = matches!(token(i.checked_sub(1)), Some(Token::Ident(_)))
+					&& matches!(token(i.checked_sub(2)), Some(Token::Colon));
+			}
+			Token::CloseBrace => attributes = false,
+			Token::Text(str) if attributes => tokens[i].1 = Token::Text(str.trim().into()),
+			_ => {}
+		}
+	}
+
+	Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn indentation() {
+		let str = "
+:head[
+    :title[Title]
+        ]
+:body[:p[
+  Hello
+]
+]";
+
+		assert_eq!(
+			format_str(str).unwrap(),
+			":head[\n\t:title[Title]\n]\n:body[\n\t:p[\n\t\tHello\n\t]\n]\n"
+		);
+	}
+
+	#[test]
+	fn keeps_comments() {
+		let str = "# Title\n&title := :title[Hi];  # The title\n\n\n<# Head #>\n:head[&title]";
+
+		assert_eq!(
+			format_str(str).unwrap(),
+			"# Title\n&title := :title[Hi]; # The title\n\n<# Head #>\n:head[&title]\n"
+		);
+	}
+
+	#[test]
+	fn one_tag_per_line() {
+		assert_eq!(
+			format_str(":body[\n:h1[Hi]:p[Hello :b[World]]  :br;]").unwrap(),
+			":body[\n\t:h1[Hi]\n\t:p[Hello :b[World]]\n\t:br;\n]\n"
+		);
+	}
+
+	#[test]
+	fn macros() {
+		let str =
+			"macr   @person{ &name  &pronouns }[\n&c:=Hello;\n&c &name]\n@person[&name:= Juni;]";
+
+		assert_eq!(
+			format_str(str).unwrap(),
+			"macr @person{&name &pronouns}[\n\t&c := Hello;\n\t&c &name\n]\n@person[&name := Juni;]\n"
+		);
+	}
+
+	#[test]
+	fn changed_meaning() {
+		assert!(check_meaning(":p[a]\n:p[b]", ":p[a]\n\n:p[\n\tb\n]", 0).is_ok());
+		assert!(matches!(
+			check_meaning(":p[a]\n:p[b]", ":p[a]\n:p[ b]", 0),
+			Err(TeaCatError::InternalFormatter(Some(1)))
+		));
+		assert!(matches!(
+			check_meaning(":p[a]\n:p[b]", ":p[a]\n:p[c]", 3),
+			Err(TeaCatError::InternalFormatter(Some(4)))
+		));
+		assert!(matches!(
+			check_meaning(":p[a]\n:p[b]", ":p[a]\n:p[b]:br;", 0),
+			Err(TeaCatError::InternalFormatter(Some(1)))
+		));
+		assert!(matches!(
+			check_meaning(":p[a]", ":p['a]", 0),
+			Err(TeaCatError::InternalFormatter(None))
+		));
+	}
+
+	#[test]
+	fn text_edges() {
+		let html = |str: &str| eval_teacat_string::<HtmlRenderer, String>(str).unwrap();
+
+		for (str, expected) in [
+			// Comments touching text do not add a space to it
+			(":p[a<#c#>b]", ":p[a<#c#>b]\n"),
+			(":p[a <#c#>b]", ":p[a <#c#>b]\n"),
+			// Spaces before a newline (or after indentation) are kept at the edges of text
+			(":ul[{\n a; \n b; }]", ":ul[{\n\ta; \n\tb;\n}]\n"),
+			(":p[\n    Hello\n    ]", ":p[\n\tHello\n ]\n"),
+			(":p[\tHello]", ":p[\tHello]\n"),
+			("&x :=  \n X;", "&x :=  \nX;\n"),
+		] {
+			let formatted = format_str(str).unwrap();
+
+			assert_eq!(formatted, expected);
+			assert_eq!(html(&formatted), html(str));
+			assert_eq!(format_str(&formatted).unwrap(), formatted);
+		}
+
+		let example = include_str!("../../test.tcat");
+		assert_eq!(html(&format_str(example).unwrap()), html(example));
+	}
+
+	#[test]
+	fn front_matter() {
+		assert_eq!(
+			format_str("+++\ntitle  =  'Hi'\n+++\n\n:title[  &title ]").unwrap(),
+			"+++\ntitle  =  'Hi'\n+++\n:title[&title]\n"
+		);
+		assert!(matches!(
+			format_str("+++\ntitle = 'Hi'\n:title[&title]"),
+			Err(TeaCatError::UnclosedFrontMatter)
+		));
+	}
+
+	#[test]
+	fn idempotent() {
+		let str = "
+# Comment
+&x := X;
+:ul[
+	{
+		a; b;
+		:h2[c];
+	}
+]
+:img{
+	src:'a.png'
+	alt:'A'
+};
+";
+		let formatted = format_str(str).unwrap();
+
+		assert_eq!(formatted, str.trim_start());
+		assert_eq!(format_str(&formatted).unwrap(), formatted);
+	}
+}
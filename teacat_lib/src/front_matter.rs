@@ -0,0 +1,163 @@
+//! Metadata written in TOML at the start of a TeaCat string, between two `+++` lines:
+//!
+//! ```tcat
+//! +++
+//! title = "About"
+//! draft = true
+//! +++
+//!
+//! :h1[&title]
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde_json::{Map, Value};
+
+use crate::{
+	data::{data_vars, from_toml_table},
+	prelude::*,
+};
+
+/// The line that starts and ends front matter.
+pub const DELIMITER: &str = "+++";
+
+/// The entries of front matter, by name.
+pub type Metadata = Map<String, Value>;
+
+/// Splits the front matter (including both delimiters) from the rest of a TeaCat string. If
+/// there is no front matter, the first string is empty.
+/// # Errors
+/// Returns an [UnclosedFrontMatter](TeaCatError::UnclosedFrontMatter) error if the front
+/// matter is never closed.
+/// # Examples
+/// ```
+/// use teacat_lib::front_matter::split_front_matter;
+///
+/// assert_eq!(
+/// 	split_front_matter("+++\ntitle = 'Hi'\n+++\n:p[]").unwrap(),
+/// 	("+++\ntitle = 'Hi'\n+++\n", ":p[]"),
+/// );
+/// assert_eq!(split_front_matter(":p[]").unwrap(), ("", ":p[]"));
+/// ```
+pub fn split_front_matter(teacat_str: &str) -> CatResult<(&str, &str)> {
+	let Some(first) = teacat_str
+		.split_inclusive('\n')
+		.next()
+		.filter(|line| line.trim_end() == DELIMITER)
+	else {
+		return Ok(("", teacat_str));
+	};
+
+	let mut end = first.len();
+	for line in teacat_str[end..].split_inclusive('\n') {
+		end += line.len();
+		if line.trim_end() == DELIMITER {
+			return Ok(teacat_str.split_at(end));
+		}
+	}
+
+	Err(TeaCatError::UnclosedFrontMatter)
+}
+
+/// Parses the front matter of a TeaCat string (if it has any). Returns its [Metadata]
+/// alongside the rest of the string, with the front matter replaced by empty lines so that
+/// lines within it are unchanged.
+/// # Errors
+/// Returns an [UnclosedFrontMatter](TeaCatError::UnclosedFrontMatter) error if the front
+/// matter is never closed, or an [InvalidFrontMatter](TeaCatError::InvalidFrontMatter) error
+/// if it is not valid TOML.
+/// # Examples
+/// ```
+/// use serde_json::json;
+/// use teacat_lib::front_matter::parse_front_matter;
+///
+/// let (metadata, rest) = parse_front_matter("+++\ndraft = true\n+++\n:p[]").unwrap();
+///
+/// assert_eq!(metadata["draft"], json!(true));
+/// assert_eq!(rest, "\n\n\n:p[]");
+/// ```
+pub fn parse_front_matter(teacat_str: &str) -> CatResult<(Metadata, String)> {
+	let (front_matter, rest) = split_front_matter(teacat_str)?;
+	if front_matter.is_empty() {
+		return Ok((Metadata::new(), rest.into()));
+	}
+
+	// The first line is left empty, so that lines within the TOML match those within the file
+	let toml = front_matter.strip_prefix(DELIMITER).unwrap_or(front_matter);
+	let toml = toml.trim_end().strip_suffix(DELIMITER).unwrap_or(toml);
+
+	let metadata = toml::from_str::<toml::Table>(toml).map_err(|err| {
+		let start = err.span().map_or(0, |span| span.start);
+		let line_start = toml[..start].rfind('\n').map_or(0, |i| i + 1);
+		let pos = Pos::new(
+			toml[..start].matches('\n').count(),
+			toml[line_start..start].chars().count(),
+		);
+		let reason = err.message().trim_end().replace('\n', "; ");
+		TeaCatError::InvalidFrontMatter(pos, reason)
+	})?;
+
+	let lines = front_matter.matches('\n').count();
+	Ok((from_toml_table(metadata), "\n".repeat(lines) + rest))
+}
+
+/// Converts [Metadata] into variables, in the same way as data files, so that `title` becomes
+/// `&title` and `author.name` becomes `&author.name`.
+pub fn metadata_vars(metadata: &Metadata) -> BTreeMap<String, ExpandedAst> {
+	metadata
+		.iter()
+		.flat_map(|(name, value)| data_vars(name, value))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+
+	#[test]
+	fn parsing() {
+		let (metadata, rest) = parse_front_matter(
+			"+++\r\ntitle = 'Home'\r\n[author]\r\nname = 'Juni'\r\n+++\r\n:p[&title]",
+		)
+		.unwrap();
+
+		assert_eq!(
+			Value::Object(metadata.clone()),
+			json!({ "title": "Home", "author": { "name": "Juni" } })
+		);
+		assert_eq!(rest, "\n\n\n\n\n:p[&title]");
+		assert_eq!(
+			metadata_vars(&metadata).keys().collect::<Vec<_>>(),
+			["author.name", "title"]
+		);
+
+		// Only a delimiter on the first line starts front matter
+		assert_eq!(parse_front_matter("\n+++\n").unwrap().1, "\n+++\n");
+		assert_eq!(parse_front_matter("+++a\n").unwrap().1, "+++a\n");
+	}
+
+	#[test]
+	fn errors() {
+		let err = |str| parse_front_matter(str).unwrap_err();
+
+		assert!(matches!(
+			err("+++\ntitle = 'Home'\n:p[]"),
+			TeaCatError::UnclosedFrontMatter
+		));
+		assert!(matches!(
+			err("+++\ntitle = 'Home'\ndraft = \n+++"),
+			TeaCatError::InvalidFrontMatter(Pos { line: 2, col: 8 }, _)
+		));
+
+		// TOML errors without a position are not mistaken for the front matter being unclosed
+		let err = TeaCatError::InvalidFrontMatter(Pos::new(0, 0), "x".into());
+		assert!(err.help_msg().contains("TOML"));
+		assert_eq!(err.labels()[0].message, "invalid here");
+		assert_eq!(
+			TeaCatError::UnclosedFrontMatter.labels()[0].message,
+			"opened here"
+		);
+	}
+}
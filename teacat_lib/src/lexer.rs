@@ -1,18 +1,34 @@
 use std::collections::VecDeque;
-use std::fmt::{Display, Formatter, Result};
+use std::fmt::{Display, Formatter, Result, Write};
 use std::mem::replace;
 
 use crate::{prelude::*, vecdeque};
+use cst::Trivia;
 
-mod str_walker;
+pub mod cst;
+pub(crate) mod str_walker;
 
-const QUOTES: &[char] = &['\'', '"'];
+pub(crate) const QUOTES: &[char] = &['\'', '"'];
 
 type Rules<T> = &'static [(&'static str, T)];
 
-/// A list of [Tokens](Token) built from a TeaCat string.
+/// A list of [Tokens](Token) built from a TeaCat string, alongside the [Pos] that each one
+/// starts at.
 #[derive(PartialEq, Eq, Debug)]
-pub struct TokenStream(pub VecDeque<(usize, Token)>);
+pub struct TokenStream(
+	pub VecDeque<(Pos, Token)>,
+	/// The position of the last [Token] removed by [TokenStream::pop], so that errors at the
+	/// end of the file can point at it.
+	Pos,
+);
+
+/// Where something starts within a TeaCat string. Lines and columns start at zero, and
+/// columns are counted in [chars](char).
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy, Default)]
+pub struct Pos {
+	pub line: usize,
+	pub col: usize,
+}
 
 /// The basic building blocks of a TeaCat file.
 #[derive(PartialEq, Eq, Debug, Clone)]
@@ -43,6 +59,8 @@ pub enum Escape {
 	CarriageReturn,
 	/// \t
 	Tab,
+	/// A backslash followed by a whitespace character, which is kept as-is
+	Whitespace(char),
 	// TODO: Unicode character escape
 }
 
@@ -52,81 +70,77 @@ pub struct StringLiteral {
 	pub content: String,
 }
 
+impl Pos {
+	pub fn new(line: usize, col: usize) -> Self {
+		Self { line, col }
+	}
+
+	/// Moves past the provided [char].
+	pub(crate) fn advance_char(&mut self, ch: char) {
+		if ch == '\n' {
+			*self = Self::new(self.line + 1, 0);
+		} else {
+			self.col += 1;
+		}
+	}
+
+	/// Moves past the provided [str].
+	pub(crate) fn advance(&mut self, str: &str) {
+		for ch in str.chars() {
+			self.advance_char(ch);
+		}
+	}
+}
+
 impl TokenStream {
-	/// Lexes a [str] into a list of [Tokens](Token).
-	pub fn lex(input: impl AsRef<str>) -> Self {
-		let mut walker = str_walker::StrWalker::new(input.as_ref());
-		let mut tokenstream = Self::default();
-		let mut current = (0, Token::empty());
+	/// Lexes a [str] into a list of [Tokens](Token), by lexing it into a [Cst] and then removing
+	/// its [Trivia].
+	/// # Errors
+	/// Returns an [Error](TeaCatError) if a string literal or multi-line comment is never closed,
+	/// or if a multi-line comment is closed without being opened.
+	pub fn lex(input: impl AsRef<str>) -> CatResult<Self> {
+		Ok(Self::from_cst(&Cst::lex(input)?))
+	}
 
-		let mut comment_nesting = 0usize;
-		let mut escaped = false;
+	/// Builds a [TokenStream] from a [Cst], removing comments and merging each run of text
+	/// (alongside any whitespace and escaped characters within it) into a single [Token].
+	pub fn from_cst(cst: &Cst) -> Self {
+		let mut tokenstream = Self::default();
+		let mut current = (Pos::default(), Token::empty());
+		// The position of the next char, which is given to each new token
+		let mut pos = Pos::default();
 
 		macro_rules! token_switcheroo {
 			($t:expr) => {
-				let current = &mut current;
-				let token = replace(current, (current.0, $t));
+				let token = replace(&mut current, (pos, $t));
 				tokenstream.push(token);
 			};
 		}
 
-		while !walker.reached_end() {
-			if walker.currently_starts_with("\n") {
-				current.0 += 1;
-			}
+		for cst_token in &cst.tokens {
+			tokenstream.push_trivia(&cst_token.leading, &mut pos, &mut current);
+			pos = cst_token.pos;
 
-			// Handling the backslash escape
-			// TODO: \n, \t, etc
-			if escaped {
-				if let Some(esc) = walker.try_each(Escape::RULES) {
-					token_switcheroo!(Token::Escape(esc));
-				} else {
-					tokenstream.push_current_ch(&mut walker, &mut current);
+			match &cst_token.token {
+				Token::Text(str) | Token::Ident(str) => {
+					for ch in str.chars() {
+						tokenstream.push_ch(ch, pos, &mut current);
+						pos.advance_char(ch);
+					}
+				}
+				token => {
+					token_switcheroo!(token.clone());
 				}
-				escaped = false;
-				continue;
-			}
-			if walker.currently_starts_with("\\") {
-				walker.jump_by("\\".len());
-				escaped = true;
-				continue;
-			}
-
-			// Multi-line comments
-			if walker.currently_starts_with("<#") {
-				walker.jump_by(2);
-				comment_nesting += 1;
-				continue;
-			} else if walker.currently_starts_with("#>") {
-				walker.jump_by(2);
-				comment_nesting -= 1;
-				continue;
-			} else if comment_nesting > 0 {
-				walker.next_char();
-				continue;
-			}
-
-			// Single-line comments
-			if walker.currently_starts_with("#") {
-				walker.jump_to_next("\n");
-				continue;
-			}
-
-			// Handling string literals
-			if matches!(current, (_, Token::Stringliteral(_))) {
-				tokenstream.push_current_ch(&mut walker, &mut current);
-				continue;
 			}
+			pos = cst_token.pos;
+			pos.advance(&cst_token.source);
 
-			// Checks for one of the operators/keywords is present
-			if let Some(token) = walker.try_each(Token::RULES) {
-				token_switcheroo!(token);
-				continue;
+			// Text after a string literal is never an identifier
+			if matches!(cst_token.token, Token::Stringliteral(_)) {
+				token_switcheroo!(Token::empty());
 			}
-
-			// No operators/keywords found, so the current char is inserted into the current token
-			tokenstream.push_current_ch(&mut walker, &mut current);
 		}
+		tokenstream.push_trivia(&cst.trailing, &mut pos, &mut current);
 
 		// Adding the current token
 		tokenstream.push(current);
@@ -136,30 +150,31 @@ impl TokenStream {
 		tokenstream
 	}
 
-	/// Removes the first [char] of the given [String] and inserts it into the
-	/// current [Token] if possible, or creates a new token if not.
-	fn push_current_ch(&mut self, input: &mut str_walker::StrWalker, current: &mut (usize, Token)) {
-		let ch = input
-			.next_char()
-			.expect("Should not yet have reached the end!");
+	/// Inserts the whitespace within [Trivia] into the current [Token], skipping comments.
+	fn push_trivia(&mut self, trivia: &[Trivia], pos: &mut Pos, current: &mut (Pos, Token)) {
+		for trivia in trivia {
+			match trivia {
+				Trivia::Whitespace(str) => {
+					for ch in str.chars() {
+						self.push_ch(ch, *pos, current);
+						pos.advance_char(ch);
+					}
+				}
+				Trivia::Comment(str) | Trivia::MultiComment(str) => pos.advance(str),
+			}
+		}
+	}
 
+	/// Inserts the provided [char] into the current [Token] if possible, or creates a new token
+	/// if not.
+	fn push_ch(&mut self, ch: char, pos: Pos, current: &mut (Pos, Token)) {
 		macro_rules! token_switcheroo {
 			($t:expr) => {
-				let token = replace(current, (current.0.clone(), $t));
+				let token = replace(current, (pos, $t));
 				self.push(token);
 			};
 		}
 
-		// Handling String Literals
-		if QUOTES.contains(&ch) && !matches!(current, (_, Token::Stringliteral(_))) {
-			token_switcheroo!(Token::Stringliteral(StringLiteral::empty(ch)));
-			return;
-		}
-		if matches!(current, (_, Token::Stringliteral(str)) if str.quotes == ch) {
-			token_switcheroo!(Token::empty());
-			return;
-		}
-
 		// If the current Token does not store text, set it to one that does.
 		// - If the current Token implies an Ident, create one.
 		// - Otherwise, create a Text.
@@ -175,15 +190,21 @@ impl TokenStream {
 		if matches!(current, (_, Token::Ident(_))) && ch.is_whitespace() {
 			token_switcheroo!(Token::empty());
 		}
+		// Leading whitespace is removed, so text starts at its first visible char
+		if !ch.is_whitespace() && matches!(current, (_, Token::Text(str)) if str.trim().is_empty())
+		{
+			current.0 = pos;
+		}
 
 		current.1.push_char(ch);
 	}
 
-	/// Converts any sequences of whitespace within [Tokens](Token) into singular spaces, and
-	/// removes any tokens consisting only of whitespace.
+	/// Converts any sequences of whitespace within text and idents into singular spaces, and
+	/// removes any consisting only of whitespace. String literals are left untouched.
 	fn clean_tokens(&mut self) {
-		self.0
-			.retain(|(_, token)| !matches!(token.string_ref(), Some(str) if str.trim().is_empty()));
+		self.0.retain(
+			|(_, token)| !matches!(token, Token::Text(str) | Token::Ident(str) if str.trim().is_empty()),
+		);
 
 		fn whitespace_check(ch: char) -> bool {
 			let ignore = ['\n', '\t'];
@@ -191,7 +212,7 @@ impl TokenStream {
 		}
 
 		for (_, token) in &mut self.0 {
-			if let Some(str) = token.string_mut() {
+			if let Token::Text(str) | Token::Ident(str) = token {
 				let starts_with = str.starts_with(whitespace_check);
 				let ends_with = str.ends_with(whitespace_check);
 
@@ -208,22 +229,22 @@ impl TokenStream {
 	}
 
 	/// Checks to see if the [TokenStream] begins with an ident. If it does, return the name of the
-	/// ident and the line number. Otherwise, return a relevant [Error](TeaCatError).
-	pub(crate) fn current_ident(&mut self) -> CatResult<(usize, String)> {
+	/// ident and its position. Otherwise, return a relevant [Error](TeaCatError).
+	pub(crate) fn current_ident(&mut self) -> CatResult<(Pos, String)> {
 		match self.pop() {
-			Some((line, Token::Ident(name))) => Ok((line, name)),
-			Some((line, token)) => Err(TeaCatError::ExpectedIdent(line, token).into()),
-			None => Err(TeaCatError::EarlyEof(0, Token::Ident("ident".into())).into()),
+			Some((pos, Token::Ident(name))) => Ok((pos, name)),
+			Some((pos, token)) => Err(TeaCatError::ExpectedIdent(pos, token)),
+			None => Err(TeaCatError::EarlyEof(self.1, Token::Ident("ident".into()))),
 		}
 	}
 
 	/// Checks to see if the [TokenStream] begins with the provided [Token]. If it does not, returns
-	/// a relevant [Error](TeaCatError).
-	pub fn expect(&mut self, token: Token) -> CatResult<()> {
+	/// a relevant [Error](TeaCatError). Otherwise, returns the position of the token.
+	pub fn expect(&mut self, token: Token) -> CatResult<Pos> {
 		match self.pop() {
-			Some((_, t)) if t == token => Ok(()),
-			Some((line, token)) => Err(TeaCatError::UnexpectedToken(line, token).into()),
-			_ => Err(TeaCatError::EarlyEof(0, token).into()),
+			Some((pos, t)) if t == token => Ok(pos),
+			Some((pos, token)) => Err(TeaCatError::UnexpectedToken(pos, token)),
+			_ => Err(TeaCatError::EarlyEof(self.1, token)),
 		}
 	}
 
@@ -232,29 +253,39 @@ impl TokenStream {
 	pub fn expect_with_err(
 		&mut self,
 		token: Token,
-		err_some: impl Fn(usize, Token) -> TeaCatError,
+		err_some: impl Fn(Pos, Token) -> TeaCatError,
 		err_none: impl Fn() -> TeaCatError,
 	) -> CatResult<()> {
 		match self.pop() {
 			Some((_, t)) if t == token => Ok(()),
-			Some((line, token)) => Err(err_some(line, token).into()),
-			_ => Err(err_none().into()),
+			Some((pos, token)) => Err(err_some(pos, token)),
+			_ => Err(err_none()),
 		}
 	}
 
-	/// Gets a [VecDeque] containing only tokens, without any line numbers.
+	/// Gets a [VecDeque] containing only tokens, without their positions.
 	pub fn tokens(&self) -> VecDeque<Token> {
 		self.0.iter().map(|(_, token)| token.clone()).collect()
 	}
 
-	/// Inserts a [Token] and its line number into the back of a [TokenStream].
-	pub fn push(&mut self, val: (usize, Token)) {
+	/// Inserts a [Token] and its position into the back of a [TokenStream].
+	pub fn push(&mut self, val: (Pos, Token)) {
 		self.0.push_back(val);
 	}
 
-	/// Removes and returns the front [Token] and its line number from a [TokenStream] (if present).
-	pub fn pop(&mut self) -> Option<(usize, Token)> {
-		self.0.pop_front()
+	/// Removes and returns the front [Token] and its position from a [TokenStream] (if present).
+	pub fn pop(&mut self) -> Option<(Pos, Token)> {
+		let popped = self.0.pop_front();
+		if let Some((pos, _)) = popped {
+			self.1 = pos;
+		}
+		popped
+	}
+
+	/// Gets the position of the last [Token] removed from the [TokenStream], which is where
+	/// errors at the end of the file are reported.
+	pub fn last_pos(&self) -> Pos {
+		self.1
 	}
 }
 
@@ -266,7 +297,12 @@ impl Default for TokenStream {
 
 impl From<VecDeque<Token>> for TokenStream {
 	fn from(value: VecDeque<Token>) -> Self {
-		Self(value.iter().map(|token| (0, token.clone())).collect())
+		Self(
+			(value.iter())
+				.map(|token| (Pos::default(), token.clone()))
+				.collect(),
+			Pos::default(),
+		)
 	}
 }
 
@@ -289,15 +325,6 @@ impl Token {
 		Self::Text("".into())
 	}
 
-	fn string_ref(&self) -> Option<&str> {
-		Some(match self {
-			Self::Text(str) | Self::Ident(str) => str,
-			Self::Stringliteral(strlit) => &strlit.content,
-
-			_ => return None,
-		})
-	}
-
 	fn string_mut(&mut self) -> Option<&mut String> {
 		Some(match self {
 			Self::Text(str) | Self::Ident(str) => str,
@@ -357,6 +384,7 @@ impl Display for Escape {
 			Self::CarriageReturn => "\r",
 			Self::Newline => "\n",
 			Self::Tab => "\t",
+			Self::Whitespace(ch) => return f.write_char(*ch),
 		})
 	}
 }
@@ -373,6 +401,20 @@ impl StringLiteral {
 	pub fn into_string(&self) -> String {
 		format!("{}{}{}", self.quotes, self.content, self.quotes)
 	}
+
+	/// Converts the [StringLiteral] into a [String] that can be safely used in a TeaCat file.
+	pub fn encode(&self) -> String {
+		let mut encoded = String::new();
+
+		for ch in self.content.chars() {
+			if ch == '\\' || ch == self.quotes {
+				encoded.push('\\');
+			}
+			encoded.push(ch);
+		}
+
+		format!("{}{encoded}{}", self.quotes, self.quotes)
+	}
 }
 
 impl From<&str> for StringLiteral {
@@ -396,7 +438,7 @@ mod tests {
 		&x
 		";
 
-		let tokenstream = TokenStream::lex(str);
+		let tokenstream = TokenStream::lex(str).unwrap();
 
 		assert_eq!(
 			tokenstream.tokens(),
@@ -412,6 +454,33 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn positions() {
+		let tokenstream = TokenStream::lex("&x :=\n\n  X\n;:p[\n'a\nb'] <# c #>&y").unwrap();
+
+		assert_eq!(
+			tokenstream
+				.0
+				.iter()
+				.map(|(pos, _)| (pos.line, pos.col))
+				.collect::<Vec<_>>(),
+			vec![
+				(0, 0),
+				(0, 1),
+				(0, 3),
+				(2, 2),
+				(3, 0),
+				(3, 1),
+				(3, 2),
+				(3, 3),
+				(4, 0),
+				(5, 2),
+				(5, 11),
+				(5, 12)
+			]
+		);
+	}
+
 	#[test]
 	fn escape() {
 		let str = "
@@ -419,7 +488,7 @@ mod tests {
 		\\&x
 		";
 
-		let tokenstream = TokenStream::lex(str);
+		let tokenstream = TokenStream::lex(str).unwrap();
 
 		assert_eq!(
 			tokenstream.tokens(),
@@ -438,7 +507,7 @@ mod tests {
 		:b[]
 		";
 
-		let tokenstream = TokenStream::lex(str);
+		let tokenstream = TokenStream::lex(str).unwrap();
 
 		assert_eq!(
 			tokenstream.tokens(),
@@ -457,7 +526,7 @@ mod tests {
 
 	#[test]
 	fn attributes() {
-		let tokenstream = TokenStream::lex(":tag{x:\"1\" y:'2'}[]");
+		let tokenstream = TokenStream::lex(":tag{x:\"1\" y:'2'}[]").unwrap();
 
 		assert_eq!(
 			tokenstream.tokens(),
@@ -481,7 +550,7 @@ mod tests {
 	#[test]
 	fn strlit() {
 		assert_eq!(
-			TokenStream::lex("'input'").tokens(),
+			TokenStream::lex("'input'").unwrap().tokens(),
 			vecdeque![Token::Stringliteral(StringLiteral::new(
 				'\'',
 				"input".into()
@@ -492,7 +561,7 @@ mod tests {
 	#[test]
 	fn whitespace() {
 		assert_eq!(
-			TokenStream::lex("a\ta").tokens(),
+			TokenStream::lex("a\ta").unwrap().tokens(),
 			vecdeque![Token::Text("a a".into())]
 		);
 	}
@@ -500,7 +569,9 @@ mod tests {
 	#[test]
 	fn macros() {
 		assert_eq!(
-			TokenStream::lex("@person[&name := Juni; &pronouns := she/her;]").tokens(),
+			TokenStream::lex("@person[&name := Juni; &pronouns := she/her;]")
+				.unwrap()
+				.tokens(),
 			vecdeque![
 				Token::At,
 				Token::Ident("person".into()),
@@ -529,7 +600,7 @@ mod tests {
 		:body[:p[\\&title]]
 		";
 
-		let tokenstream = TokenStream::lex(str);
+		let tokenstream = TokenStream::lex(str).unwrap();
 
 		assert_eq!(
 			tokenstream.tokens(),
@@ -564,4 +635,92 @@ mod tests {
 			]
 		);
 	}
+
+	#[test]
+	fn escaped_quotes() {
+		assert_eq!(
+			TokenStream::lex("\\\"quoted\\\"").unwrap().tokens(),
+			vecdeque![Token::Text("\"quoted\"".into())]
+		);
+	}
+
+	#[test]
+	fn strlit_contents() {
+		assert_eq!(
+			TokenStream::lex("'a  #b\\'\\n'").unwrap().tokens(),
+			vecdeque![Token::Stringliteral(StringLiteral::new(
+				'\'',
+				"a  #b'\n".into()
+			))]
+		);
+	}
+
+	#[test]
+	fn escaped_whitespace() {
+		assert_eq!(
+			TokenStream::lex("a\\  b").unwrap().tokens(),
+			vecdeque![
+				Token::Text("a".into()),
+				Token::Escape(Escape::Whitespace(' ')),
+				Token::Text(" b".into()),
+			]
+		);
+	}
+
+	#[test]
+	fn matches_cst() {
+		let fixtures = [
+			"&x := X;\n:p{class:'a\\'b' id:\"c\"}[Hello,  &x\\n\\&y]",
+			"macr @m{&a}[\n\t:b[&a] # Comment\n]\n@m[&a := 1;]\n{a; :i[b];}",
+			"<# Comment\n<# nested #> \\#> #>\n:p[a <# inline #> b]\\ \\\n",
+		];
+
+		// Everything except text (and the whitespace and comments around it) should be the
+		// same as within the Cst, in the same order and at the same positions
+		let not_text =
+			|(_, token): &(Pos, Token)| !matches!(token, Token::Text(_) | Token::Ident(_));
+		for str in fixtures {
+			let cst = Cst::lex(str).unwrap();
+			let from_cst: Vec<(Pos, Token)> = cst
+				.tokens
+				.iter()
+				.map(|cst_token| (cst_token.pos, cst_token.token.clone()))
+				.filter(not_text)
+				.collect();
+			let tokenstream: Vec<(Pos, Token)> = TokenStream::lex(str)
+				.unwrap()
+				.0
+				.into_iter()
+				.filter(not_text)
+				.collect();
+
+			assert_eq!(tokenstream, from_cst, "{str}");
+		}
+	}
+
+	#[test]
+	fn comments() {
+		assert_eq!(
+			TokenStream::lex("a <# \\#> #> b # c\nd").unwrap().tokens(),
+			vecdeque![Token::Text("a b d".into())]
+		);
+	}
+
+	#[test]
+	fn unterminated() {
+		let err = |str| TokenStream::lex(str).unwrap_err();
+
+		assert!(matches!(
+			err(":a[]\n:b{x:'1}[]"),
+			TeaCatError::UnterminatedStr(Pos { line: 1, col: 5 }, '\'')
+		));
+		assert!(matches!(
+			err("<# <# #>\n:a[]"),
+			TeaCatError::UnterminatedComment(Pos { line: 0, col: 0 })
+		));
+		assert!(matches!(
+			err(":a[]\n\n #>"),
+			TeaCatError::UnmatchedCommentClose(Pos { line: 2, col: 1 })
+		));
+	}
 }
@@ -0,0 +1,329 @@
+use std::fmt::{Display, Formatter, Result};
+
+use super::{Escape, Pos, QUOTES, StringLiteral, Token, str_walker::StrWalker};
+use crate::prelude::*;
+
+/// A lossless list of [Tokens](Token) built from a TeaCat string.
+///
+/// Unlike a [TokenStream], all whitespace, comments and escapes are kept as [Trivia], so
+/// the original string can be reproduced exactly by displaying the [Cst].
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Cst {
+	pub tokens: Vec<CstToken>,
+	/// Any [Trivia] after the final [Token].
+	pub trailing: Vec<Trivia>,
+}
+
+/// A [Token] alongside the [Trivia] before it and its original spelling.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CstToken {
+	pub pos: Pos,
+	pub leading: Vec<Trivia>,
+	pub token: Token,
+	/// The [Token] exactly as it was written.
+	pub source: String,
+}
+
+/// Parts of a TeaCat string that do not produce [Tokens](Token).
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Trivia {
+	Whitespace(String),
+	/// A single-line comment, including the '#'.
+	Comment(String),
+	/// A multi-line comment, including the '<#' and '#>'.
+	MultiComment(String),
+}
+
+impl Cst {
+	/// Lexes a [str] into a [Cst].
+	/// # Errors
+	/// Returns the same [Errors](TeaCatError) as [TokenStream::lex].
+	pub fn lex(input: impl AsRef<str>) -> CatResult<Self> {
+		let input = input.as_ref();
+		let mut walker = StrWalker::new(input);
+		let mut cst = Self::default();
+		let mut leading = vec![];
+		// The position of the next char
+		let mut pos = Pos::default();
+
+		while let Some(ch) = walker.peek_char() {
+			let start = walker.index();
+			let start_pos = pos;
+
+			// Trivia
+			if ch.is_whitespace() {
+				while walker.peek_char().is_some_and(char::is_whitespace) {
+					walker.next_char();
+				}
+				let str = walker.since(start);
+				pos.advance(str);
+				leading.push(Trivia::Whitespace(str.into()));
+				continue;
+			}
+			if walker.currently_starts_with("<#") {
+				multi_comment(&mut walker, pos)?;
+				let str = walker.since(start);
+				pos.advance(str);
+				leading.push(Trivia::MultiComment(str.into()));
+				continue;
+			}
+			if walker.currently_starts_with("#>") {
+				return Err(TeaCatError::UnmatchedCommentClose(pos));
+			}
+			if ch == '#' {
+				while walker.peek_char().is_some_and(|ch| ch != '\n') {
+					walker.next_char();
+				}
+				let str = walker.since(start);
+				pos.advance(str);
+				leading.push(Trivia::Comment(str.into()));
+				continue;
+			}
+
+			// Tokens
+			let token = if ch == '\\' {
+				walker.jump_by("\\".len());
+				if let Some(esc) = walker.try_each(Escape::RULES) {
+					Token::Escape(esc)
+				} else if let Some(ch) = walker.peek_char().filter(|ch| ch.is_whitespace()) {
+					walker.next_char();
+					Token::Escape(Escape::Whitespace(ch))
+				} else {
+					let ch = walker.next_char();
+					Token::Text(ch.map(String::from).unwrap_or_default())
+				}
+			} else if QUOTES.contains(&ch) {
+				Token::Stringliteral(string_literal(&mut walker, pos)?)
+			} else if let Some(token) = walker.try_each(Token::RULES) {
+				token
+			} else {
+				while walker.peek_char().is_some_and(|ch| !ends_word(&walker, ch)) {
+					walker.next_char();
+				}
+				let str = walker.since(start).to_string();
+
+				match cst.tokens.last() {
+					Some(prev) if prev.token.creates_ident() => Token::Ident(str),
+					_ => Token::Text(str),
+				}
+			};
+
+			let source = walker.since(start);
+			pos.advance(source);
+			cst.tokens.push(CstToken {
+				pos: start_pos,
+				leading: std::mem::take(&mut leading),
+				token,
+				source: source.into(),
+			});
+		}
+
+		cst.trailing = leading;
+		Ok(cst)
+	}
+
+	/// Gets an iterator over the [Tokens](Token) within the [Cst], without any [Trivia].
+	pub fn tokens(&self) -> impl Iterator<Item = &Token> {
+		self.tokens.iter().map(|cst_token| &cst_token.token)
+	}
+}
+
+impl Display for Cst {
+	fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+		for token in &self.tokens {
+			token.fmt(f)?;
+		}
+		for trivia in &self.trailing {
+			trivia.fmt(f)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl Display for CstToken {
+	fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+		for trivia in &self.leading {
+			trivia.fmt(f)?;
+		}
+
+		f.write_str(&self.source)
+	}
+}
+
+impl Trivia {
+	/// Gets the [Trivia] exactly as it was written.
+	pub fn source(&self) -> &str {
+		match self {
+			Self::Whitespace(str) | Self::Comment(str) | Self::MultiComment(str) => str,
+		}
+	}
+}
+
+impl Display for Trivia {
+	fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+		f.write_str(self.source())
+	}
+}
+
+/// Walks past a (potentially nested) multi-line comment, which starts at the provided
+/// position. Backslashes escape the next character, so `\#>` does not close the comment.
+fn multi_comment(walker: &mut StrWalker, mut pos: Pos) -> CatResult<()> {
+	let mut comments = vec![];
+
+	loop {
+		let start = walker.index();
+
+		if walker.currently_starts_with("\\") {
+			walker.jump_by("\\".len());
+			walker.next_char();
+		} else if walker.currently_starts_with("<#") {
+			walker.jump_by(2);
+			comments.push(pos);
+		} else if walker.currently_starts_with("#>") {
+			walker.jump_by(2);
+			comments.pop();
+
+			if comments.is_empty() {
+				return Ok(());
+			}
+		} else if walker.next_char().is_none() {
+			let pos = comments.pop().expect("Should be within a comment");
+			return Err(TeaCatError::UnterminatedComment(pos));
+		}
+
+		pos.advance(walker.since(start));
+	}
+}
+
+/// Walks past a string literal, which starts at the provided position, returning it.
+fn string_literal(walker: &mut StrWalker, start: Pos) -> CatResult<StringLiteral> {
+	let quotes = walker.next_char().expect("Should be at a quote");
+	let mut strlit = StringLiteral::empty(quotes);
+
+	loop {
+		let Some(ch) = walker.next_char() else {
+			return Err(TeaCatError::UnterminatedStr(start, quotes));
+		};
+
+		match ch {
+			ch if ch == quotes => return Ok(strlit),
+			'\\' => {
+				if let Some(esc) = walker.try_each(Escape::RULES) {
+					strlit.content.push_str(&esc.to_string());
+				} else if let Some(ch) = walker.next_char() {
+					strlit.content.push(ch);
+				}
+			}
+			ch => strlit.content.push(ch),
+		}
+	}
+}
+
+/// Whether or not the provided [char] (found at the current position of the [StrWalker])
+/// ends a word of [Text](Token::Text) or an [Ident](Token::Ident).
+fn ends_word(walker: &StrWalker, ch: char) -> bool {
+	ch.is_whitespace()
+		|| ch == '\\'
+		|| ch == '#'
+		|| walker.currently_starts_with("<#")
+		|| QUOTES.contains(&ch)
+		|| Token::RULES
+			.iter()
+			.any(|(key, _)| walker.currently_starts_with(key))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn lossless() {
+		let str = "
+		# Comment
+		&x := X; <# Multi-line <# nested #>
+		comment #>
+		:p{class:'a\\'b'}[
+			\\&x\\n &x	:br;
+		]
+		";
+
+		assert_eq!(Cst::lex(str).unwrap().to_string(), str);
+	}
+
+	#[test]
+	fn trivia() {
+		let cst = Cst::lex(":a[x] # Comment\n").unwrap();
+
+		assert_eq!(
+			cst.tokens().cloned().collect::<Vec<Token>>(),
+			vec![
+				Token::Colon,
+				Token::Ident("a".into()),
+				Token::OpenBracket,
+				Token::Text("x".into()),
+				Token::CloseBracket,
+			]
+		);
+		assert_eq!(
+			cst.trailing,
+			vec![
+				Trivia::Whitespace(" ".into()),
+				Trivia::Comment("# Comment".into()),
+				Trivia::Whitespace("\n".into()),
+			]
+		);
+	}
+
+	#[test]
+	fn escapes() {
+		let cst = Cst::lex("\\n\\&").unwrap();
+
+		assert_eq!(
+			cst.tokens,
+			vec![
+				CstToken {
+					pos: Pos::new(0, 0),
+					leading: vec![],
+					token: Token::Escape(Escape::Newline),
+					source: "\\n".into(),
+				},
+				CstToken {
+					pos: Pos::new(0, 2),
+					leading: vec![],
+					token: Token::Text("&".into()),
+					source: "\\&".into(),
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn errors() {
+		let err = |str| Cst::lex(str).unwrap_err();
+
+		assert!(matches!(
+			err("\n<# <# #>"),
+			TeaCatError::UnterminatedComment(Pos { line: 1, col: 0 })
+		));
+		assert!(matches!(
+			err("x '"),
+			TeaCatError::UnterminatedStr(Pos { line: 0, col: 2 }, '\'')
+		));
+		assert!(matches!(
+			err("#>"),
+			TeaCatError::UnmatchedCommentClose(Pos { line: 0, col: 0 })
+		));
+	}
+
+	#[test]
+	fn escaped_comment_close() {
+		let cst = Cst::lex("<# \\#> #>").unwrap();
+
+		assert!(cst.tokens.is_empty());
+		assert_eq!(
+			cst.trailing,
+			vec![Trivia::MultiComment("<# \\#> #>".into())]
+		);
+	}
+}
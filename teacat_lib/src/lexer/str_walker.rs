@@ -38,20 +38,30 @@ impl<'input> StrWalker<'input> {
 		}
 	}
 
-	/// Whether or not the internal index has reached or passed the length of the [str].
-	pub fn reached_end(&self) -> bool {
-		self.to_walk.len() <= self.index
+	/// Gets the current [char] without increasing the internal index.
+	pub fn peek_char(&self) -> Option<char> {
+		self.to_walk.get(self.index..)?.chars().next()
 	}
 
-	/// Jumps to the next instance of the target.
-	pub fn jump_to_next(&mut self, target: &str) {
-		loop {
-			self.next_char();
+	/// Gets the [char] that is the provided amount of [chars](char) after the current one,
+	/// without increasing the internal index.
+	pub fn peek_nth(&self, n: usize) -> Option<char> {
+		self.to_walk.get(self.index..)?.chars().nth(n)
+	}
 
-			if self.currently_starts_with(target) || self.reached_end() {
-				return;
-			}
-		}
+	/// Gets the internal index of the [StrWalker].
+	pub fn index(&self) -> usize {
+		self.index
+	}
+
+	/// Gets the section of the [str] from the provided index to the internal index.
+	pub fn since(&self, start: usize) -> &'input str {
+		&self.to_walk[start..self.index.min(self.to_walk.len())]
+	}
+
+	/// Whether or not the internal index has reached or passed the length of the [str].
+	pub fn reached_end(&self) -> bool {
+		self.to_walk.len() <= self.index
 	}
 
 	/// Checks if the internal index is at the start of a pattern matching the target [str].
@@ -2,7 +2,7 @@
 //! This crate contains basic tools for working with TeaCat files.
 //!
 //! ```rust
-//! # fn hidden() -> anyhow::Result<()> {
+//! # fn hidden() -> Result<(), teacat_lib::error::TeaCatError> {
 //! use teacat_lib::prelude::*;
 //!
 //! let teacat_str = "
@@ -18,7 +18,7 @@
 //! "
 //! .to_string();
 //!
-//! let tokenstream = TokenStream::lex(teacat_str);
+//! let tokenstream = TokenStream::lex(teacat_str)?;
 //! let ast = Ast::parse(tokenstream)?;
 //! let expanded = ExpandedAst::expand(ast)?;
 //! let html = HtmlRenderer::render(expanded);
@@ -34,14 +34,61 @@
 
 #![allow(clippy::tabs_in_doc_comments)]
 
+use std::{fs, path::Path};
+
+use lexer::{Escape, QUOTES, Token, str_walker::StrWalker};
 use prelude::*;
 
+pub mod data;
 pub mod error;
 pub mod expanded;
+pub mod formatter;
+pub mod front_matter;
 pub mod lexer;
 pub mod parser;
+pub mod template;
+#[cfg(any(test, feature = "testing"))]
+#[doc(hidden)]
+pub mod testing;
+
+/// A TeaCat string, alongside the name of the file it came from (if known).
+///
+/// Errors that occur within a [Source] are returned as a [SourceError], so that the name can
+/// be shown alongside them.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Source {
+	pub name: Option<String>,
+	pub text: String,
+}
+
+impl Source {
+	pub fn named(name: impl Into<String>, text: impl Into<String>) -> Self {
+		Self {
+			name: Some(name.into()),
+			text: text.into(),
+		}
+	}
+}
+
+impl From<&str> for Source {
+	fn from(text: &str) -> Self {
+		text.to_string().into()
+	}
+}
+
+impl From<&String> for Source {
+	fn from(text: &String) -> Self {
+		text.clone().into()
+	}
+}
+
+impl From<String> for Source {
+	fn from(text: String) -> Self {
+		Self { name: None, text }
+	}
+}
 
-/// Evaluates a TeaCat string.
+/// Evaluates a TeaCat string. To name the string in errors, use [eval_teacat_source].
 /// # Examples
 /// ```
 /// use teacat_lib::prelude::*;
@@ -56,13 +103,106 @@ pub mod parser;
 pub fn eval_teacat_string<Rend: Renderer<Out>, Out>(
 	teacat_string: impl AsRef<str>,
 ) -> CatResult<Out> {
-	let tokenstream = TokenStream::lex(teacat_string);
-	let ast = Ast::parse(tokenstream)?;
-	let expanded = ExpandedAst::expand(ast)?;
-	Ok(Rend::render(expanded))
+	eval_teacat_string_with_diagnostics::<Rend, Out>(teacat_string, &mut Diagnostics::new())
+}
+
+/// Evaluates a TeaCat string, adding any warnings to the provided [Diagnostics].
+/// # Examples
+/// ```
+/// use teacat_lib::prelude::*;
+///
+/// let mut diagnostics = Diagnostics::new();
+/// let html =
+/// 	eval_teacat_string_with_diagnostics::<HtmlRenderer, String>("&x := X;", &mut diagnostics);
+///
+/// assert_eq!(html.unwrap(), "<!DOCTYPE html><html></html>");
+/// assert_eq!(diagnostics.count(Severity::Warning), 1);
+/// ```
+pub fn eval_teacat_string_with_diagnostics<Rend: Renderer<Out>, Out>(
+	teacat_string: impl AsRef<str>,
+	diagnostics: &mut Diagnostics,
+) -> CatResult<Out> {
+	eval_teacat_string_with::<Rend, Out>(teacat_string, &ExpandOptions::default(), diagnostics)
+}
+
+/// Evaluates a TeaCat string using the provided [ExpandOptions], adding any warnings to the
+/// provided [Diagnostics]. When evaluating the same string many times, a [Template] avoids
+/// lexing and parsing it each time.
+/// # Examples
+/// ```
+/// use std::collections::BTreeMap;
+/// use teacat_lib::prelude::*;
+///
+/// let options = ExpandOptions {
+/// 	vars: BTreeMap::from([("name".into(), ExpandedAst::text("TeaCat"))]),
+/// 	..ExpandOptions::default()
+/// };
+/// let html = eval_teacat_string_with::<HtmlRenderer, String>(
+/// 	":p[Hello, &name]",
+/// 	&options,
+/// 	&mut Diagnostics::new(),
+/// );
+///
+/// assert_eq!(html.unwrap(), "<!DOCTYPE html><html><p>Hello, TeaCat</p></html>");
+/// ```
+pub fn eval_teacat_string_with<Rend: Renderer<Out>, Out>(
+	teacat_string: impl AsRef<str>,
+	options: &ExpandOptions,
+	diagnostics: &mut Diagnostics,
+) -> CatResult<Out> {
+	eval_teacat_source::<Rend, Out>(teacat_string.as_ref(), options, diagnostics)
+		.map_err(TeaCatError::from)
+}
+
+/// Evaluates a [Source] in the same way as [eval_teacat_string_with], returning its name
+/// alongside any error.
+/// # Examples
+/// ```
+/// use teacat_lib::prelude::*;
+///
+/// let source = Source::named("index.tcat", "&x");
+/// let err = eval_teacat_source::<HtmlRenderer, String>(
+/// 	source,
+/// 	&ExpandOptions::default(),
+/// 	&mut Diagnostics::new(),
+/// )
+/// .unwrap_err();
+///
+/// assert_eq!(err.name.as_deref(), Some("index.tcat"));
+/// assert!(matches!(err.error, TeaCatError::UndefinedVar(Pos { line: 0, .. }, ..)));
+/// assert!(err.err_plain("&x").contains("╭─[index.tcat:1:1]"));
+/// ```
+pub fn eval_teacat_source<Rend: Renderer<Out>, Out>(
+	source: impl Into<Source>,
+	options: &ExpandOptions,
+	diagnostics: &mut Diagnostics,
+) -> Result<Out, SourceError> {
+	Template::parse_with_diagnostics(source, diagnostics)?
+		.render_with::<Rend, Out>(options, diagnostics)
+}
+
+/// Reads and evaluates a TeaCat file.
+/// # Errors
+/// Returns an [Io](TeaCatError::Io) error if the file cannot be read, alongside any errors
+/// from [eval_teacat_string].
+/// # Examples
+/// ```
+/// use teacat_lib::prelude::*;
+///
+/// let result = eval_teacat_file::<HtmlRenderer, String>("does_not_exist.tcat");
+///
+/// assert!(matches!(result, Err(TeaCatError::Io(..))));
+/// ```
+pub fn eval_teacat_file<Rend: Renderer<Out>, Out>(path: impl AsRef<Path>) -> CatResult<Out> {
+	let path = path.as_ref();
+	let teacat_string = fs::read_to_string(path).map_err(|err| TeaCatError::io(path, err))?;
+	eval_teacat_string::<Rend, Out>(teacat_string)
 }
 
 /// Encodes a string so that it can be safely used in a TeaCat file.
+///
+/// Anything that the [TokenStream] would treat as an operator, keyword, comment or string
+/// literal is escaped, as is any whitespace that would otherwise be removed or merged.
 /// # Examples
 /// ```
 /// use teacat_lib::prelude::*;
@@ -74,32 +214,49 @@ pub fn eval_teacat_string<Rend: Renderer<Out>, Out>(
 /// 	"\\:head\\[\\]".to_string(),
 /// );
 /// ```
-pub fn encode_str(str: impl Into<String>) -> String {
-	#[rustfmt::skip]
-	const TOKENS: &[&str] = &[
-		"\\", 
-		"[", 
-		"]", 
-		"{", 
-		"}", 
-		"macr", 
-		":=", 
-		":", 
-		";", 
-		"&", 
-		"@",
-	];
-
-	let mut str = str.into();
-
-	for token in TOKENS {
-		str = str
-			.split(token)
-			.collect::<Vec<&str>>()
-			.join(&("\\".to_owned() + token));
+pub fn encode_str(str: impl AsRef<str>) -> String {
+	let str = str.as_ref();
+	let mut walker = StrWalker::new(str);
+	let mut encoded = String::with_capacity(str.len());
+
+	// Whitespace-only text is removed, so it must all be escaped
+	let only_whitespace = str.trim().is_empty();
+	let mut prev = None;
+
+	while let Some(ch) = walker.peek_char() {
+		let escape = Escape::RULES
+			.iter()
+			.find(|(_, esc)| esc.to_string().starts_with(ch));
+
+		if let Some((key, _)) = escape {
+			encoded.push('\\');
+			encoded.push_str(key);
+		} else {
+			// Comments ("#" and "<#") are escaped by escaping the "#" itself
+			let special = ch == '\\'
+				|| ch == '#' || QUOTES.contains(&ch)
+				|| Token::RULES
+					.iter()
+					.any(|(key, _)| walker.currently_starts_with(key));
+
+			// Sequences of whitespace are merged, so only lone spaces can be left as-is
+			let merged_whitespace = ch.is_whitespace()
+				&& (ch != ' '
+					|| only_whitespace
+					|| prev.is_some_and(char::is_whitespace)
+					|| walker.peek_nth(1).is_some_and(char::is_whitespace));
+
+			if special || merged_whitespace {
+				encoded.push('\\');
+			}
+			encoded.push(ch);
+		}
+
+		prev = Some(ch);
+		walker.next_char();
 	}
 
-	str
+	encoded
 }
 
 /// A macro to create a [VecDeque](std::collections::VecDeque).
@@ -120,15 +277,24 @@ macro_rules! vecdeque {
 }
 
 pub mod prelude {
-	pub use crate::error::TeaCatError;
+	pub use crate::error::{
+		CatResult, ReportOptions, SourceError, TeaCatError,
+		diagnostics::{Diagnostic, Diagnostics, Severity, TeaCatWarning},
+	};
 	pub use crate::expanded::{
-		ExpandedAst, ExpandedNode, ExpandedTag,
-		renderer::{Renderer, html::HtmlRenderer, tcat::TeaCatRenderer},
+		EnvAccess, ExpandOptions, ExpandedAst, ExpandedNode, ExpandedTag,
+		renderer::{
+			Renderer,
+			html::{HtmlRenderer, PrettyHtmlRenderer},
+			tcat::TeaCatRenderer,
+		},
 	};
-	pub use crate::lexer::TokenStream;
+	pub use crate::formatter::format_str;
+	pub use crate::lexer::{Pos, TokenStream, cst::Cst};
 	pub use crate::parser::{Ast, Attributes};
-	pub use crate::{encode_str, eval_teacat_string};
-
-	// Meow
-	pub(crate) use anyhow::Result as CatResult;
+	pub use crate::template::Template;
+	pub use crate::{
+		Source, encode_str, eval_teacat_file, eval_teacat_source, eval_teacat_string,
+		eval_teacat_string_with, eval_teacat_string_with_diagnostics,
+	};
 }
@@ -1,12 +1,12 @@
 use std::{
-	collections::{BTreeMap, VecDeque},
+	collections::{BTreeMap, HashMap, VecDeque},
 	vec,
 };
 
-use anyhow::Ok;
-
 use crate::{
-	lexer::{StringLiteral, Token},
+	error::diagnostics::{Diagnostics, TeaCatWarning},
+	expanded::renderer::html::VOID,
+	lexer::{Pos, StringLiteral, Token},
 	prelude::*,
 	vecdeque,
 };
@@ -15,15 +15,16 @@ use crate::{
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Ast(pub VecDeque<AstNode>);
 
-/// A single node within an [Ast].
+/// A single node within an [Ast]. Variables and macros are stored alongside the position of
+/// their `&` or `@`.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum AstNode {
 	Text(String),
 	Tag(Tag),
 	Var(Var),
-	AccessVar(usize, String),
+	AccessVar(Pos, String),
 	Macr(Macr),
-	AccessMacr(usize, Vec<Var>, String),
+	AccessMacr(Pos, Vec<Var>, String),
 	Array(Vec<Ast>),
 }
 
@@ -36,12 +37,14 @@ pub struct Tag {
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Var {
+	pub pos: Pos,
 	pub name: String,
 	pub contents: Ast,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Macr {
+	pub pos: Pos,
 	pub name: String,
 	pub args: Vec<String>,
 	pub contents: Ast,
@@ -50,37 +53,52 @@ pub struct Macr {
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct Attributes(pub BTreeMap<String, StringLiteral>);
 
+/// Where [Ast::parse_until] stops, alongside the [Token] that opened the group being parsed
+/// (which is reported if the file ends first).
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Until {
+	token: Token,
+	/// Where the group was opened.
+	pos: Pos,
+	open: Token,
+	close: Token,
+}
+
 impl Ast {
 	pub fn empty() -> Self {
 		vecdeque![].into()
 	}
 
-	pub fn parse(mut tokenstream: TokenStream) -> CatResult<Self> {
-		Self::parse_until(&mut tokenstream, None)
+	pub fn parse(tokenstream: TokenStream) -> CatResult<Self> {
+		Self::parse_with_diagnostics(tokenstream, &mut Diagnostics::new())
 	}
 
-	fn parse_until(tokenstream: &mut TokenStream, until: Option<Token>) -> CatResult<Self> {
-		let mut nodes = vec![];
-		let mut current_line = 0;
-
-		while !tokenstream.0.is_empty() {
-			let (line, token) = tokenstream
-				.0
-				.pop_front()
-				.expect("TokenStream should not be empty");
+	/// Parses a [TokenStream], adding any warnings to the provided [Diagnostics].
+	pub fn parse_with_diagnostics(
+		mut tokenstream: TokenStream,
+		diagnostics: &mut Diagnostics,
+	) -> CatResult<Self> {
+		Self::parse_until(&mut tokenstream, None, diagnostics)
+	}
 
-			current_line = line;
+	fn parse_until(
+		tokenstream: &mut TokenStream,
+		until: Option<Until>,
+		diagnostics: &mut Diagnostics,
+	) -> CatResult<Self> {
+		let mut nodes = vec![];
 
-			if matches!(until, Some(ref t) if *t == token) {
+		while let Some((pos, token)) = tokenstream.pop() {
+			if matches!(until, Some(ref until) if until.token == token) {
 				return Ok(Self(nodes.into()));
 			}
 
 			nodes.push(match token {
-				Token::Andpersand => var(tokenstream)?,
-				Token::Colon => tag(tokenstream)?,
-				Token::OpenBrace => array(tokenstream)?,
-				Token::Macr => macr(tokenstream)?,
-				Token::At => access_macr(tokenstream)?,
+				Token::Andpersand => var(pos, tokenstream, diagnostics)?,
+				Token::Colon => tag(pos, tokenstream, diagnostics)?,
+				Token::OpenBrace => array(pos, tokenstream, diagnostics)?,
+				Token::Macr => macr(tokenstream, diagnostics)?,
+				Token::At => access_macr(pos, tokenstream, diagnostics)?,
 
 				// The remaining tokens are either text themselves or only useful if
 				// explicitly required by another, so they can be safely converted
@@ -89,10 +107,9 @@ impl Ast {
 			});
 		}
 
-		if let Some(token) = until {
-			Err(TeaCatError::EarlyEof(current_line, token).into())
-		} else {
-			Ok(Self(nodes.into()))
+		match until {
+			Some(until) => Err(until.unclosed()),
+			None => Ok(Self(nodes.into())),
 		}
 	}
 }
@@ -110,28 +127,62 @@ impl AstNode {
 }
 
 impl Var {
-	pub fn new(name: String, tokenstream: &mut TokenStream) -> CatResult<Self> {
+	/// Parses the contents of a variable, after its `:=`.
+	pub fn new(
+		pos: Pos,
+		name: String,
+		walrus: Pos,
+		tokenstream: &mut TokenStream,
+		diagnostics: &mut Diagnostics,
+	) -> CatResult<Self> {
 		Ok(Self {
+			pos,
 			name,
-			contents: Ast::parse_until(tokenstream, Some(Token::SemiColon))?,
+			contents: Ast::parse_until(
+				tokenstream,
+				Some(Until::closed_by(walrus, Token::Walrus, Token::SemiColon)),
+				diagnostics,
+			)?,
 		})
 	}
 }
 
+impl Until {
+	/// Stops at the [Token] that closes the group.
+	fn closed_by(pos: Pos, open: Token, close: Token) -> Self {
+		Self {
+			token: close.clone(),
+			pos,
+			open,
+			close,
+		}
+	}
+
+	fn unclosed(self) -> TeaCatError {
+		TeaCatError::Unclosed(self.pos, self.open, self.close)
+	}
+}
+
 impl Attributes {
 	pub fn new() -> Self {
 		Self(BTreeMap::new())
 	}
 
-	fn parse(tokenstream: &mut TokenStream) -> CatResult<Self> {
+	/// Parses the attributes after an opening brace, which is at the provided position.
+	fn parse(
+		brace: Pos,
+		tokenstream: &mut TokenStream,
+		diagnostics: &mut Diagnostics,
+	) -> CatResult<Self> {
 		let mut attributes = BTreeMap::new();
-		let mut current_line = 0;
+		// Where each attribute was first set
+		let mut positions = HashMap::new();
+		let unclosed = || TeaCatError::Unclosed(brace, Token::OpenBrace, Token::CloseBrace);
 
 		loop {
-			let Some((line, token)) = tokenstream.pop() else {
-				return Err(TeaCatError::EarlyEof(current_line, Token::CloseBrace).into());
+			let Some((pos, token)) = tokenstream.pop() else {
+				return Err(unclosed());
 			};
-			current_line = line;
 
 			match token {
 				Token::CloseBrace => break,
@@ -139,30 +190,33 @@ impl Attributes {
 					tokenstream.expect_with_err(
 						Token::Colon,
 						TeaCatError::UnexpectedAttr,
-						|| TeaCatError::EarlyEof(line, Token::Colon),
+						unclosed,
 					)?;
 
 					let val = match tokenstream.pop() {
 						Some((_, Token::Stringliteral(val))) => val,
 
-						Some((line, token)) => {
-							return Err(TeaCatError::UnexpectedAttr(line, token).into());
+						Some((pos, token)) => {
+							return Err(TeaCatError::UnexpectedAttr(pos, token));
 						}
 
-						_ => {
-							return Err(TeaCatError::EarlyEof(
-								line,
-								Token::Stringliteral(StringLiteral::empty('"')),
-							)
-							.into());
-						}
+						_ => return Err(unclosed()),
 					};
 
-					attributes.insert(key, val);
+					let key = key.trim();
+					if attributes.insert(key.into(), val).is_some() {
+						diagnostics.push(TeaCatWarning::DuplicateAttr(
+							pos,
+							key.into(),
+							positions[key],
+						));
+					} else {
+						positions.insert(key.to_string(), pos);
+					}
 				}
 
 				other => {
-					return Err(TeaCatError::UnexpectedAttr(line, other).into());
+					return Err(TeaCatError::UnexpectedAttr(pos, other));
 				}
 			}
 		}
@@ -177,20 +231,25 @@ impl From<BTreeMap<String, StringLiteral>> for Attributes {
 	}
 }
 
-fn macr(tokenstream: &mut TokenStream) -> CatResult<AstNode> {
-	tokenstream.expect(Token::At)?;
-	let (line, name) = tokenstream.current_ident()?;
-	tokenstream.expect(Token::OpenBrace)?;
+fn macr(tokenstream: &mut TokenStream, diagnostics: &mut Diagnostics) -> CatResult<AstNode> {
+	let pos = tokenstream.expect(Token::At)?;
+	let (_, name) = tokenstream.current_ident()?;
+	let brace = tokenstream.expect(Token::OpenBrace)?;
 
 	let mut macr = Macr {
+		pos,
 		name,
 		args: vec![],
 		contents: Ast(vecdeque![]),
 	};
 
 	loop {
-		let Some((line, token)) = tokenstream.pop() else {
-			return Err(TeaCatError::EarlyEof(line, Token::CloseBrace).into());
+		let Some((pos, token)) = tokenstream.pop() else {
+			return Err(TeaCatError::Unclosed(
+				brace,
+				Token::OpenBrace,
+				Token::CloseBrace,
+			));
 		};
 
 		match token {
@@ -200,41 +259,56 @@ fn macr(tokenstream: &mut TokenStream) -> CatResult<AstNode> {
 				macr.args.push(name);
 			}
 
-			other => return Err(TeaCatError::UnexpectedToken(line, other).into()),
+			other => return Err(TeaCatError::UnexpectedToken(pos, other)),
 		}
 	}
 
-	tokenstream.expect(Token::OpenBracket)?;
+	let bracket = tokenstream.expect(Token::OpenBracket)?;
 
-	macr.contents = Ast::parse_until(tokenstream, Some(Token::CloseBracket))?;
+	macr.contents = Ast::parse_until(
+		tokenstream,
+		Some(Until::closed_by(
+			bracket,
+			Token::OpenBracket,
+			Token::CloseBracket,
+		)),
+		diagnostics,
+	)?;
 	Ok(AstNode::Macr(macr))
 }
 
-fn access_macr(tokenstream: &mut TokenStream) -> CatResult<AstNode> {
-	let (line, name) = tokenstream.current_ident()?;
-	tokenstream.expect(Token::OpenBracket)?;
+/// Parses a macro being used, after its `@` (at the provided position).
+fn access_macr(
+	at: Pos,
+	tokenstream: &mut TokenStream,
+	diagnostics: &mut Diagnostics,
+) -> CatResult<AstNode> {
+	let (_, name) = tokenstream.current_ident()?;
+	let bracket = tokenstream.expect(Token::OpenBracket)?;
+	let unclosed = || TeaCatError::Unclosed(bracket, Token::OpenBracket, Token::CloseBracket);
 
 	let mut vars = vec![];
 
 	loop {
-		let Some((line, token)) = tokenstream.pop() else {
-			return Err(TeaCatError::EarlyEof(line, Token::CloseBracket).into());
+		let Some((pos, token)) = tokenstream.pop() else {
+			return Err(unclosed());
 		};
 
 		match token {
 			Token::CloseBracket => break,
 			Token::Andpersand => {
-				let var = match var(tokenstream)? {
+				let var = match var(pos, tokenstream, diagnostics)? {
 					AstNode::Var(var) => var,
-					AstNode::AccessVar(line, name) => {
+					AstNode::AccessVar(pos, name) => {
 						let var = Var {
+							pos,
 							name: name.clone(),
-							contents: Ast(vecdeque![AstNode::AccessVar(line, name)]),
+							contents: Ast(vecdeque![AstNode::AccessVar(pos, name)]),
 						};
 						tokenstream.expect_with_err(
 							Token::SemiColon,
 							TeaCatError::ExpectedSemicolon,
-							|| TeaCatError::EarlyEof(line, Token::SemiColon),
+							unclosed,
 						)?;
 						var
 					}
@@ -243,41 +317,79 @@ fn access_macr(tokenstream: &mut TokenStream) -> CatResult<AstNode> {
 
 				vars.push(var);
 			}
-			other => return Err(TeaCatError::EarlyEof(line, other).into()),
+			other => return Err(TeaCatError::UnexpectedToken(pos, other)),
 		}
 	}
 
-	Ok(AstNode::AccessMacr(line, vars, name))
+	Ok(AstNode::AccessMacr(at, vars, name))
 }
 
-fn var(tokenstream: &mut TokenStream) -> CatResult<AstNode> {
-	let (line, name) = tokenstream.current_ident()?;
+/// Parses a variable being defined or used, after its `&` (at the provided position).
+fn var(
+	andpersand: Pos,
+	tokenstream: &mut TokenStream,
+	diagnostics: &mut Diagnostics,
+) -> CatResult<AstNode> {
+	let (_, name) = tokenstream.current_ident()?;
 
 	// DONT pop from front until we know that the token is one we want
-	Ok(if let Some((_, Token::Walrus)) = tokenstream.0.front() {
-		// Now we know that it's safe to remove
-		tokenstream.pop();
-		AstNode::Var(Var::new(name, tokenstream)?)
-	} else {
-		AstNode::AccessVar(line, name)
-	})
+	Ok(
+		if let Some(&(walrus, Token::Walrus)) = tokenstream.0.front() {
+			// Now we know that it's safe to remove
+			tokenstream.pop();
+			AstNode::Var(Var::new(
+				andpersand,
+				name,
+				walrus,
+				tokenstream,
+				diagnostics,
+			)?)
+		} else {
+			AstNode::AccessVar(andpersand, name)
+		},
+	)
 }
 
-fn tag(tokenstream: &mut TokenStream) -> CatResult<AstNode> {
-	let (line, name) = tokenstream.current_ident()?;
+/// Parses a tag, after its `:` (at the provided position).
+fn tag(
+	colon: Pos,
+	tokenstream: &mut TokenStream,
+	diagnostics: &mut Diagnostics,
+) -> CatResult<AstNode> {
+	let (_, name) = tokenstream.current_ident()?;
 
 	let mut attributes = Attributes::new();
-	if let Some((_, Token::OpenBrace)) = tokenstream.0.front() {
+	if let Some((brace, Token::OpenBrace)) = tokenstream.0.front() {
+		let brace = *brace;
 		tokenstream.pop();
-		attributes = Attributes::parse(tokenstream)?;
+		attributes = Attributes::parse(brace, tokenstream, diagnostics)?;
 	}
 
 	let contents = match tokenstream.pop() {
 		Some((_, Token::SemiColon)) => Ast::empty(),
-		Some((_, Token::OpenBracket)) => Ast::parse_until(tokenstream, Some(Token::CloseBracket))?,
+		Some((bracket, Token::OpenBracket)) => {
+			let contents = Ast::parse_until(
+				tokenstream,
+				Some(Until::closed_by(
+					bracket,
+					Token::OpenBracket,
+					Token::CloseBracket,
+				)),
+				diagnostics,
+			)?;
+			if contents.0.is_empty() && VOID.contains(&name.as_str()) {
+				diagnostics.push(TeaCatWarning::EmptyVoidTag(colon, name.clone()));
+			}
+			contents
+		}
 
-		Some((line, token)) => return Err(TeaCatError::UnexpectedToken(line, token).into()),
-		None => return Err(TeaCatError::EarlyEof(line, Token::SemiColon).into()),
+		Some((pos, token)) => return Err(TeaCatError::UnexpectedToken(pos, token)),
+		None => {
+			return Err(TeaCatError::EarlyEof(
+				tokenstream.last_pos(),
+				Token::SemiColon,
+			));
+		}
 	};
 
 	Ok(AstNode::Tag(Tag {
@@ -287,14 +399,28 @@ fn tag(tokenstream: &mut TokenStream) -> CatResult<AstNode> {
 	}))
 }
 
-fn array(tokenstream: &mut TokenStream) -> CatResult<AstNode> {
+/// Parses the items of an array, after an opening brace at the provided position.
+fn array(
+	brace: Pos,
+	tokenstream: &mut TokenStream,
+	diagnostics: &mut Diagnostics,
+) -> CatResult<AstNode> {
 	let mut array = vec![];
+	// Each item ends with a semicolon, but it is the brace that is left unclosed
+	let until = Until {
+		token: Token::SemiColon,
+		..Until::closed_by(brace, Token::OpenBrace, Token::CloseBrace)
+	};
 
 	loop {
 		match tokenstream.0.front() {
 			Some((_, Token::CloseBrace)) => break,
-			Some(_) => array.push(Ast::parse_until(tokenstream, Some(Token::SemiColon))?),
-			None => return Err(TeaCatError::EarlyEof(0, Token::SemiColon).into()),
+			Some(_) => array.push(Ast::parse_until(
+				tokenstream,
+				Some(until.clone()),
+				diagnostics,
+			)?),
+			None => return Err(until.unclosed()),
 		}
 	}
 
@@ -328,10 +454,11 @@ mod tests {
 			ast,
 			vecdeque![
 				AstNode::Var(Var {
+					pos: Pos::default(),
 					name: "x".into(),
 					contents: vecdeque![AstNode::Text(" X".into())].into()
 				}),
-				AstNode::AccessVar(0, "x".into()),
+				AstNode::AccessVar(Pos::default(), "x".into()),
 			]
 			.into()
 		);
@@ -414,7 +541,7 @@ mod tests {
 
 	#[test]
 	fn attributes() {
-		let ast = Ast::parse(TokenStream::lex(":tag{x:\"1\"y:\"2\"}[]")).unwrap();
+		let ast = Ast::parse(TokenStream::lex(":tag{x:\"1\"y:\"2\"}[]").unwrap()).unwrap();
 
 		assert_eq!(
 			ast,
@@ -440,12 +567,13 @@ mod tests {
 		:body[:p[\\&title]]
 		";
 
-		let ast = Ast::parse(TokenStream::lex(str)).unwrap();
+		let ast = Ast::parse(TokenStream::lex(str).unwrap()).unwrap();
 
 		assert_eq!(
 			ast,
 			vecdeque![
 				AstNode::Var(Var {
+					pos: Pos::new(1, 2),
 					name: "title".into(),
 					contents: vecdeque![AstNode::Tag(Tag {
 						name: "title".into(),
@@ -457,7 +585,7 @@ mod tests {
 				AstNode::Tag(Tag {
 					name: "head".into(),
 					attributes: Attributes::new(),
-					contents: vecdeque![AstNode::AccessVar(2, "title".into())].into()
+					contents: vecdeque![AstNode::AccessVar(Pos::new(2, 8), "title".into())].into()
 				}),
 				AstNode::Tag(Tag {
 					name: "body".into(),
@@ -473,4 +601,74 @@ mod tests {
 			.into()
 		);
 	}
+
+	#[test]
+	fn warnings() {
+		let mut diagnostics = Diagnostics::new();
+		let tokenstream = TokenStream::lex(":a{x:'1' x:'2'}[]\n:br[]").unwrap();
+		Ast::parse_with_diagnostics(tokenstream, &mut diagnostics).unwrap();
+
+		assert!(matches!(
+			diagnostics.0.as_slice(),
+			[
+				Diagnostic::Warning(TeaCatWarning::DuplicateAttr(
+					Pos { line: 0, col: 9 },
+					key,
+					Pos { line: 0, col: 3 }
+				)),
+				Diagnostic::Warning(TeaCatWarning::EmptyVoidTag(Pos { line: 1, col: 0 }, name)),
+			] if key == "x" && name == "br"
+		));
+	}
+
+	#[test]
+	fn eof_locations() {
+		let err = |str| Ast::parse(TokenStream::lex(str).unwrap()).unwrap_err();
+		let unclosed =
+			|line, open, close| TeaCatError::Unclosed(Pos::new(line, 0), open, close).to_string();
+
+		// The innermost group that was never closed is reported
+		assert_eq!(
+			err(":html[\n\t:body[\n\t\t:p[Hi]\n\t\t:p[\n\n").to_string(),
+			unclosed(3, Token::OpenBracket, Token::CloseBracket)
+		);
+		assert_eq!(
+			err(":p[]\n&x := :b[X]\n").to_string(),
+			unclosed(1, Token::Walrus, Token::SemiColon)
+		);
+		assert_eq!(
+			err("&a :=\n{\n\ta;\n\tb").to_string(),
+			unclosed(1, Token::OpenBrace, Token::CloseBrace)
+		);
+		assert_eq!(
+			err(":a{\nhref:'x'\n").to_string(),
+			unclosed(0, Token::OpenBrace, Token::CloseBrace)
+		);
+		assert_eq!(
+			err("macr @m{&x}\n[\n&x").to_string(),
+			unclosed(1, Token::OpenBracket, Token::CloseBracket)
+		);
+		assert_eq!(
+			err("\n@m[&x := 1;\n&y").to_string(),
+			unclosed(1, Token::OpenBracket, Token::CloseBracket)
+		);
+
+		// Otherwise, the error points at the last token
+		assert!(matches!(
+			err(":a[]\n\n:"),
+			TeaCatError::EarlyEof(Pos { line: 2, col: 0 }, Token::Ident(_))
+		));
+		assert!(matches!(
+			err("\n:p # No contents"),
+			TeaCatError::EarlyEof(Pos { line: 1, col: 1 }, Token::SemiColon)
+		));
+		assert!(matches!(
+			err("macr @m{}\n"),
+			TeaCatError::EarlyEof(Pos { line: 0, col: 8 }, Token::OpenBracket)
+		));
+		assert!(matches!(
+			err("\n@m[x]"),
+			TeaCatError::UnexpectedToken(Pos { line: 1, col: 3 }, Token::Text(_))
+		));
+	}
 }
@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+
+use crate::{
+	front_matter::{Metadata, metadata_vars, parse_front_matter},
+	prelude::*,
+};
+
+/// A TeaCat string that has already been lexed and parsed, so that it can be rendered many
+/// times (such as with different variables) without doing either again.
+///
+/// Any [front matter](crate::front_matter) is parsed into [Metadata], which is defined as
+/// variables when the template is rendered.
+/// # Examples
+/// ```
+/// use std::collections::BTreeMap;
+/// use teacat_lib::prelude::*;
+///
+/// let template = Template::parse(":p[Hello, &name]").unwrap();
+///
+/// for name in ["Juni", "Sam"] {
+/// 	let context = ExpandOptions {
+/// 		vars: BTreeMap::from([("name".into(), ExpandedAst::text(name))]),
+/// 		..ExpandOptions::default()
+/// 	};
+/// 	let html = template
+/// 		.render_with::<HtmlRenderer, String>(&context, &mut Diagnostics::new())
+/// 		.unwrap();
+///
+/// 	assert_eq!(html, format!("<!DOCTYPE html><html><p>Hello, {name}</p></html>"));
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+	name: Option<String>,
+	metadata: Metadata,
+	/// The [Metadata] as variables, so that it is only converted once.
+	metadata_vars: BTreeMap<String, ExpandedAst>,
+	ast: Ast,
+}
+
+impl Template {
+	/// Lexes and parses a TeaCat string, which can be given a name by passing a [Source].
+	pub fn parse(teacat_string: impl Into<Source>) -> Result<Self, SourceError> {
+		Self::parse_with_diagnostics(teacat_string, &mut Diagnostics::new())
+	}
+
+	/// Lexes and parses a TeaCat string, adding any warnings to the provided [Diagnostics].
+	pub fn parse_with_diagnostics(
+		teacat_string: impl Into<Source>,
+		diagnostics: &mut Diagnostics,
+	) -> Result<Self, SourceError> {
+		let source = teacat_string.into();
+		let template = Self {
+			ast: Ast::empty(),
+			metadata: Metadata::new(),
+			metadata_vars: BTreeMap::new(),
+			name: source.name,
+		};
+
+		let (metadata, body) =
+			parse_front_matter(&source.text).map_err(|err| template.named(err))?;
+		let ast = TokenStream::lex(body)
+			.and_then(|tokenstream| Ast::parse_with_diagnostics(tokenstream, diagnostics))
+			.map_err(|err| template.named(err))?;
+
+		Ok(Self {
+			ast,
+			metadata_vars: metadata_vars(&metadata),
+			metadata,
+			..template
+		})
+	}
+
+	/// Renders the template without any predefined variables other than its [Metadata].
+	pub fn render<Rend: Renderer<Out>, Out>(&self) -> Result<Out, SourceError> {
+		self.render_with::<Rend, Out>(&ExpandOptions::default(), &mut Diagnostics::new())
+	}
+
+	/// Renders the template, using the provided [ExpandOptions] as the context (such as the
+	/// variables defined within it) and adding any warnings to the provided [Diagnostics].
+	pub fn render_with<Rend: Renderer<Out>, Out>(
+		&self,
+		context: &ExpandOptions,
+		diagnostics: &mut Diagnostics,
+	) -> Result<Out, SourceError> {
+		self.expand_with(context, diagnostics).map(Rend::render)
+	}
+
+	/// Expands the template in the same way as [Template::render_with], without rendering it.
+	/// Entries of the [Metadata] are defined alongside (and replace) the variables within the
+	/// context.
+	pub fn expand_with(
+		&self,
+		context: &ExpandOptions,
+		diagnostics: &mut Diagnostics,
+	) -> Result<ExpandedAst, SourceError> {
+		ExpandedAst::expand_borrowed(&self.ast, context, &self.metadata_vars, diagnostics)
+			.map_err(|err| self.named(err))
+	}
+
+	/// The name of the template, if it was given one.
+	pub fn name(&self) -> Option<&str> {
+		self.name.as_deref()
+	}
+
+	/// The entries of the template's front matter, which is empty if it has none.
+	pub fn metadata(&self) -> &Metadata {
+		&self.metadata
+	}
+
+	/// The parsed template.
+	pub fn ast(&self) -> &Ast {
+		&self.ast
+	}
+
+	fn named(&self, err: TeaCatError) -> SourceError {
+		SourceError::new(self.name(), err)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use super::*;
+
+	#[test]
+	fn contexts() {
+		let template = Template::parse(Source::named("page.tcat", ":title[&title]")).unwrap();
+		let context = |title: &str| ExpandOptions {
+			vars: BTreeMap::from([("title".into(), ExpandedAst::text(title))]),
+			..ExpandOptions::default()
+		};
+		let render = |context| {
+			template.render_with::<TeaCatRenderer, String>(&context, &mut Diagnostics::new())
+		};
+
+		assert_eq!(render(context("Home")).unwrap(), ":title[Home]");
+		assert_eq!(render(context("About")).unwrap(), ":title[About]");
+
+		let err = template.render::<TeaCatRenderer, String>().unwrap_err();
+		assert_eq!(err.name.as_deref(), Some("page.tcat"));
+		assert!(matches!(
+			err.error,
+			TeaCatError::UndefinedVar(Pos { line: 0, .. }, ..)
+		));
+
+		let err = Template::parse(Source::named("broken.tcat", ":p[")).unwrap_err();
+		assert_eq!(err.name.as_deref(), Some("broken.tcat"));
+		assert!(Template::parse(":p[").unwrap_err().name.is_none());
+	}
+
+	#[test]
+	fn metadata() {
+		let template = Template::parse(
+			"+++\ntitle = 'Home'\ndraft = true\n+++\n:title[&title]\n&draft := no;",
+		)
+		.unwrap();
+		let context = ExpandOptions {
+			vars: BTreeMap::from([("title".into(), ExpandedAst::text("Untitled"))]),
+			..ExpandOptions::default()
+		};
+		let mut diagnostics = Diagnostics::new();
+
+		assert_eq!(template.metadata()["draft"], true);
+		assert_eq!(
+			template
+				.render_with::<TeaCatRenderer, String>(&context, &mut diagnostics)
+				.unwrap(),
+			":title[Home]"
+		);
+
+		// Metadata can be replaced within the page, which is only warned about as it is unused
+		assert_eq!(diagnostics.count(Severity::Warning), 1);
+
+		let err = Template::parse("+++\ntitle = Home\n+++\n&x").unwrap_err();
+		assert!(matches!(
+			err.error,
+			TeaCatError::InvalidFrontMatter(Pos { line: 1, .. }, _)
+		));
+		let err = Template::parse("+++\n\n+++\n&x")
+			.and_then(|template| template.render::<TeaCatRenderer, String>())
+			.unwrap_err();
+		assert!(matches!(
+			err.error,
+			TeaCatError::UndefinedVar(Pos { line: 3, .. }, ..)
+		));
+	}
+}
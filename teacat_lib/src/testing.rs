@@ -0,0 +1,22 @@
+//! Helpers shared by the tests of TeaCat and its command line tool.
+
+use std::{env, fs, path::PathBuf, process};
+
+/// A temporary directory, which is removed once dropped (even if a test fails).
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+	/// Creates a directory that is unique to the test and process, so that tests being run at
+	/// the same time never share one.
+	pub fn new(test: &str) -> Self {
+		let dir = env::temp_dir().join(format!("teacat_{test}_{}", process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		Self(dir)
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.0);
+	}
+}